@@ -0,0 +1,147 @@
+//! Runs `Cloudflare::update_dns` against a tiny local server that answers like Cloudflare's API,
+//! so provider code can be tried out without credentials:
+//!
+//! ```text
+//! cargo run --example cloudflare_stub
+//! ```
+//!
+//! It shows the requests an update makes and the responses it expects back, and doubles as a
+//! template for checking a new provider the same way: swap in its config and the answers its API
+//! gives.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::JoinHandle;
+
+use box_dyn_dns::update_dns::cloudflare::{Cloudflare, CloudflareConfig};
+use box_dyn_dns::{UpdateDns, UpdateDnsCreator};
+use serde_json::json;
+
+/// A request as the stub saw it.
+#[derive(Debug)]
+struct StubRequest {
+    method: String,
+    /// Path and query, e.g. `/zones/z/dns_records?name=a.example.com&type=A`.
+    target: String,
+    body: Vec<u8>,
+}
+
+/// Answers one connection per response, in order, then hands back what was asked of it.
+fn serve(responses: Vec<serde_json::Value>) -> (String, JoinHandle<Vec<StubRequest>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let stub = std::thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|response| {
+                let (stream, _) = listener.accept().unwrap();
+                answer(stream, &response)
+            })
+            .collect()
+    });
+    (base_url, stub)
+}
+
+fn answer(stream: TcpStream, response: &serde_json::Value) -> StubRequest {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    let response = response.to_string();
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.len(),
+        response
+    )
+    .unwrap();
+    StubRequest {
+        method,
+        target,
+        body,
+    }
+}
+
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    // Cloudflare wraps every result in the same envelope
+    let (base_url, stub) = serve(vec![
+        // GET /zones/{zone_id}/dns_records?name=...&type=...
+        json!({
+            "success": true,
+            "errors": [],
+            "result": [{
+                "id": "rec1",
+                "name": "home.example.com",
+                "type": "A",
+                "content": "198.51.100.1",
+                "ttl": 300,
+                "proxied": false,
+            }],
+        }),
+        // PUT /zones/{zone_id}/dns_records/{record_id}
+        json!({"success": true, "errors": [], "result": {}}),
+    ]);
+    let config: CloudflareConfig = serde_json::from_value(json!({
+        "base_url": base_url,
+        "zone_id": "z",
+        "api_token": "t",
+        "retry": {"max_retries": 0},
+    }))?;
+
+    let outcome = Cloudflare::from_config(config)
+        .update_dns(
+            "home.example.com".to_string(),
+            "203.0.113.5".parse()?,
+            false,
+        )
+        .await?;
+    println!("{}", outcome);
+
+    let requests = stub.join().unwrap();
+    for request in &requests {
+        println!("{} {}", request.method, request.target);
+    }
+    assert_eq!(requests[0].method, "GET");
+    assert_eq!(
+        requests[0].target,
+        "/zones/z/dns_records?name=home.example.com&type=A"
+    );
+    assert_eq!(requests[1].method, "PUT");
+    assert_eq!(requests[1].target, "/zones/z/dns_records/rec1");
+    // The shape of an update: the record's type, name and new content, with its TTL and proxy
+    // setting carried over
+    let body: serde_json::Value = serde_json::from_slice(&requests[1].body)?;
+    println!("{}", body);
+    assert_eq!(
+        body,
+        json!({
+            "type": "A",
+            "name": "home.example.com",
+            "content": "203.0.113.5",
+            "ttl": 300,
+            "proxied": false,
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, FixedOffset};
+use color_eyre::eyre::WrapErr;
+use log::{debug, warn};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// The addresses last successfully applied to each DNS name, at most one per family.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct IpCache {
+    /// When each name last got a new address, by family, in seconds since the Unix epoch.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_changed_at"
+    )]
+    changed_at: BTreeMap<String, BTreeMap<String, u64>>,
+    /// When the provider last said each name's record was modified, by family, as RFC 3339.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    modified_on: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(flatten)]
+    entries: BTreeMap<String, Vec<IpAddr>>,
+}
+
+/// `$XDG_CACHE_HOME/box-dyn-dns/last_ip`, falling back to `~/.cache`.
+pub fn default_cache_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("box-dyn-dns").join("last_ip"))
+}
+
+impl IpCache {
+    /// A missing or unreadable cache is treated as empty, it only ever saves work.
+    pub(crate) fn load(path: &Path) -> IpCache {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                debug!("Not using IP cache {}: {}", path.display(), e);
+                return IpCache::default();
+            }
+        };
+        serde_json::from_slice(&contents).unwrap_or_else(|e| {
+            warn!("Ignoring corrupt IP cache {}: {}", path.display(), e);
+            IpCache::default()
+        })
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let contents = serde_json::to_vec(self).wrap_err("Failed to serialize IP cache")?;
+        std::fs::write(path, contents)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub(crate) fn contains(&self, name: &str, ip: IpAddr) -> bool {
+        self.entries
+            .get(name)
+            .is_some_and(|addresses| addresses.contains(&ip))
+    }
+
+    /// Replaces the cached address of the same family as `ip`.
+    pub(crate) fn record(&mut self, name: &str, ip: IpAddr) {
+        let addresses = self.entries.entry(name.to_string()).or_default();
+        addresses.retain(|existing| existing.is_ipv4() != ip.is_ipv4());
+        addresses.push(ip);
+    }
+
+    /// The cached address of the same family as `ip`, whatever it is.
+    pub(crate) fn last(&self, name: &str, ip: IpAddr) -> Option<IpAddr> {
+        self.entries
+            .get(name)?
+            .iter()
+            .copied()
+            .find(|existing| existing.is_ipv4() == ip.is_ipv4())
+    }
+
+    /// Notes that `name` got `ip` at `at`, which only holds back the same family.
+    pub(crate) fn record_change(&mut self, name: &str, ip: IpAddr, at: SystemTime) {
+        let secs = at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        self.changed_at
+            .entry(name.to_string())
+            .or_default()
+            .insert(family(ip).to_string(), secs);
+    }
+
+    /// How long ago `name` last got a new address of `ip`'s family, if that's less than `interval`
+    /// before `now`.
+    pub(crate) fn changed_within(
+        &self,
+        name: &str,
+        ip: IpAddr,
+        interval: Duration,
+        now: SystemTime,
+    ) -> Option<Duration> {
+        let changed_at = self.changed_at.get(name)?.get(family(ip))?;
+        let changed_at = UNIX_EPOCH + Duration::from_secs(*changed_at);
+        // A change "in the future" means the clock went back, which shouldn't hold it forever
+        let since = now.duration_since(changed_at).ok()?;
+        Some(since).filter(|since| *since < interval)
+    }
+
+    /// When the record of `ip`'s family for `name` was last modified, as of the last run.
+    pub(crate) fn modified_on(&self, name: &str, ip: IpAddr) -> Option<DateTime<FixedOffset>> {
+        let modified_on = self.modified_on.get(name)?.get(family(ip))?;
+        DateTime::parse_from_rfc3339(modified_on).ok()
+    }
+
+    /// Replaces the last-modified time for `ip`'s family, or forgets it if it isn't known.
+    pub(crate) fn record_modified_on(
+        &mut self,
+        name: &str,
+        ip: IpAddr,
+        modified_on: Option<DateTime<FixedOffset>>,
+    ) {
+        match modified_on {
+            Some(modified_on) => {
+                self.modified_on
+                    .entry(name.to_string())
+                    .or_default()
+                    .insert(family(ip).to_string(), modified_on.to_rfc3339());
+            }
+            None => {
+                if let Some(families) = self.modified_on.get_mut(name) {
+                    families.remove(family(ip));
+                    if families.is_empty() {
+                        self.modified_on.remove(name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Caches written before families were kept apart have one time per name, which is taken to be
+/// when both families last changed.
+fn deserialize_changed_at<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, BTreeMap<String, u64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ChangedAt {
+        ByFamily(BTreeMap<String, u64>),
+        Both(u64),
+    }
+
+    Ok(BTreeMap::<String, ChangedAt>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, changed_at)| {
+            let by_family = match changed_at {
+                ChangedAt::ByFamily(by_family) => by_family,
+                ChangedAt::Both(secs) => ["ipv4", "ipv6"]
+                    .iter()
+                    .map(|family| (family.to_string(), secs))
+                    .collect(),
+            };
+            (name, by_family)
+        })
+        .collect())
+}
+
+fn family(ip: IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "ipv4",
+        IpAddr::V6(_) => "ipv6",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_held_for_the_interval() {
+        let mut cache: IpCache =
+            serde_json::from_str(r#"{"a.example.com": ["203.0.113.5"]}"#).unwrap();
+        let v4 = "203.0.113.5".parse().unwrap();
+        let v6 = "2001:db8::5".parse().unwrap();
+        let changed = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let interval = Duration::from_secs(600);
+        assert_eq!(
+            cache.changed_within("a.example.com", v4, interval, changed),
+            None
+        );
+
+        cache.record_change("a.example.com", v4, changed);
+        let cache: IpCache = serde_json::from_slice(&serde_json::to_vec(&cache).unwrap()).unwrap();
+
+        assert!(cache.contains("a.example.com", v4));
+        assert_eq!(
+            cache.changed_within(
+                "a.example.com",
+                v4,
+                interval,
+                changed + Duration::from_secs(60)
+            ),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            cache.changed_within("a.example.com", v4, interval, changed + interval),
+            None
+        );
+        assert_eq!(
+            cache.changed_within("a.example.com", v6, interval, changed),
+            None
+        );
+        assert_eq!(
+            cache.changed_within("b.example.com", v4, interval, changed),
+            None
+        );
+
+        // Written before families were kept apart
+        let old: IpCache =
+            serde_json::from_str(r#"{"changed_at": {"a.example.com": 1000000}}"#).unwrap();
+        assert_eq!(
+            old.changed_within("a.example.com", v6, interval, changed),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn modification_times_are_kept_by_family() {
+        let mut cache = IpCache::default();
+        let v4 = "203.0.113.5".parse().unwrap();
+        let v6 = "2001:db8::5".parse().unwrap();
+        let modified_on = DateTime::parse_from_rfc3339("2024-03-01T10:00:00.123456Z").unwrap();
+
+        cache.record_modified_on("a.example.com", v4, Some(modified_on));
+        let mut cache: IpCache =
+            serde_json::from_slice(&serde_json::to_vec(&cache).unwrap()).unwrap();
+
+        assert_eq!(cache.modified_on("a.example.com", v4), Some(modified_on));
+        assert_eq!(cache.modified_on("a.example.com", v6), None);
+        cache.record_modified_on("a.example.com", v4, None);
+        assert_eq!(cache.modified_on("a.example.com", v4), None);
+        assert_eq!(serde_json::to_string(&cache).unwrap(), "{}");
+    }
+}
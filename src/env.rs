@@ -0,0 +1,61 @@
+use color_eyre::eyre::{eyre, WrapErr};
+
+/// Fills in a secret from the environment. In order of precedence:
+///
+/// 1. An explicit value is used as-is.
+/// 2. A `${VAR}` placeholder is replaced with `VAR`, which must be set.
+/// 3. An absent (empty) value is taken from `fallback_var`, which must be set.
+pub(crate) fn resolve_env(
+    value: &mut String,
+    field: &str,
+    fallback_var: &str,
+) -> color_eyre::Result<()> {
+    let var = match value
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+    {
+        Some(var) => var.to_string(),
+        None if value.is_empty() => fallback_var.to_string(),
+        None => return Ok(()),
+    };
+    *value = std::env::var(&var).map_err(|e| {
+        eyre!(
+            "`{}` is taken from environment variable {}, but it is {}",
+            field,
+            var,
+            match e {
+                std::env::VarError::NotPresent => "not set",
+                std::env::VarError::NotUnicode(_) => "not valid unicode",
+            }
+        )
+    })?;
+    Ok(())
+}
+
+/// Replaces each `<field>_file` setting with `<field>`, read from the file it names with
+/// surrounding whitespace trimmed, for secrets mounted as files by Docker or Kubernetes. Setting
+/// both forms of a field is an error.
+pub(crate) fn read_secret_files(
+    settings: &mut serde_json::Map<String, serde_json::Value>,
+) -> color_eyre::Result<()> {
+    let files: Vec<(String, String)> = settings
+        .iter()
+        .filter_map(|(key, value)| Some((key.strip_suffix("_file")?, value.as_str()?)))
+        .map(|(field, path)| (field.to_string(), path.to_string()))
+        .collect();
+    for (field, path) in files {
+        let file_key = format!("{}_file", field);
+        if settings.contains_key(&field) {
+            return Err(eyre!(
+                "Only one of `{}` and `{}` can be set",
+                field,
+                file_key
+            ));
+        }
+        settings.remove(&file_key);
+        let contents = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read `{}` from {}", field, path))?;
+        settings.insert(field, contents.trim().into());
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use chrono::{SecondsFormat, Utc};
+use color_eyre::eyre::WrapErr;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// Where to keep a log of address changes, and how many of the latest to keep.
+#[derive(Debug, Clone)]
+pub struct HistoryFile {
+    pub path: PathBuf,
+    pub keep: usize,
+}
+
+/// One record pointed at a new address.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct HistoryEntry {
+    pub timestamp: String,
+    pub dns_name: String,
+    pub old_ip: Option<IpAddr>,
+    pub new_ip: IpAddr,
+    pub provider: String,
+    /// `created` if there was no record before, otherwise `updated`.
+    pub outcome: String,
+}
+
+impl HistoryEntry {
+    pub(crate) fn now(
+        dns_name: &str,
+        old_ip: Option<IpAddr>,
+        new_ip: IpAddr,
+        provider: String,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            dns_name: dns_name.to_string(),
+            old_ip,
+            new_ip,
+            provider,
+            outcome: if old_ip.is_some() {
+                "updated"
+            } else {
+                "created"
+            }
+            .to_string(),
+        }
+    }
+}
+
+impl HistoryFile {
+    /// Only for looking back on, so any problem with the file is logged rather than failing the run.
+    pub(crate) fn append(&self, entries: Vec<HistoryEntry>) {
+        if entries.is_empty() {
+            return;
+        }
+        let mut history = self.load();
+        history.extend(entries);
+        let excess = history.len().saturating_sub(self.keep);
+        history.drain(..excess);
+        if let Err(e) = self.save(&history) {
+            warn!("Failed to save change history: {:#}", e);
+        }
+    }
+
+    fn load(&self) -> Vec<HistoryEntry> {
+        let contents = match std::fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                debug!("Starting new history {}: {}", self.path.display(), e);
+                return Vec::new();
+            }
+        };
+        serde_json::from_slice(&contents).unwrap_or_else(|e| {
+            warn!(
+                "Starting over from corrupt history {}: {}",
+                self.path.display(),
+                e
+            );
+            Vec::new()
+        })
+    }
+
+    fn save(&self, history: &[HistoryEntry]) -> color_eyre::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let contents =
+            serde_json::to_vec_pretty(history).wrap_err("Failed to serialize history")?;
+        std::fs::write(&self.path, contents)
+            .wrap_err_with(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn only_the_latest_changes_are_kept() {
+        let history = HistoryFile {
+            path: std::env::temp_dir()
+                .join(format!("box-dyn-dns-test-{}-history", std::process::id())),
+            keep: 2,
+        };
+        let _ = std::fs::remove_file(&history.path);
+        let entry = |last_octet| {
+            HistoryEntry::now(
+                "a.example.com",
+                Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, last_octet - 1))),
+                IpAddr::V4(Ipv4Addr::new(203, 0, 113, last_octet)),
+                "Stdout".to_string(),
+            )
+        };
+
+        history.append(vec![entry(1)]);
+        history.append(vec![entry(2), entry(3)]);
+        let saved = history.load();
+        let _ = std::fs::remove_file(&history.path);
+
+        let new_ips: Vec<IpAddr> = saved.iter().map(|entry| entry.new_ip).collect();
+        assert_eq!(new_ips, [entry(2).new_ip, entry(3).new_ip]);
+        assert_eq!(saved[0].outcome, "updated");
+    }
+}
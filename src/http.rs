@@ -0,0 +1,495 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use color_eyre::eyre::WrapErr;
+use log::warn;
+use reqwest::header::HeaderValue;
+use reqwest::{Certificate, Client, ClientBuilder, Method, NoProxy, Proxy, StatusCode};
+use serde::de::{DeserializeOwned, Error};
+use serde::{Deserialize, Deserializer, Serialize};
+use url::Url;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+pub(crate) const DEFAULT_USER_AGENT: &str = concat!("box-dyn-dns/", env!("CARGO_PKG_VERSION"));
+
+/// HTTP settings for provider requests. They can be set at the top level of the secrets file and
+/// overridden in each provider's section.
+#[derive(Deserialize, Default, Clone)]
+pub struct HttpOptions {
+    /// Seconds to wait for a response before giving up, 30 if not set anywhere.
+    pub timeout_secs: Option<u64>,
+    /// Proxy for all requests, `http`, `https`, `socks5` or `socks5h` (which resolves names through
+    /// the proxy too). When not set anywhere, `HTTPS_PROXY` / `HTTP_PROXY` and `NO_PROXY` are used.
+    #[serde(default, deserialize_with = "deserialize_proxy")]
+    pub proxy: Option<Url>,
+    /// Comma-separated hosts, domains and IP ranges to reach without going through `proxy`.
+    pub no_proxy: Option<String>,
+    /// Sent with every request, `box-dyn-dns/<version>` by default.
+    #[serde(default, deserialize_with = "deserialize_user_agent")]
+    pub user_agent: Option<String>,
+    /// Accept any TLS certificate, for self-hosted servers behind an internal one. Leaves the
+    /// requests open to interception, so prefer `ca_cert`.
+    pub insecure_tls: Option<bool>,
+    /// A PEM file with a root certificate to trust alongside the system's, e.g. a private CA.
+    #[serde(default, deserialize_with = "deserialize_ca_cert")]
+    pub ca_cert: Option<CaCert>,
+}
+
+impl Debug for HttpOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // A proxy URL can have a password in it
+        let proxy = self.proxy.as_ref().map(|proxy| {
+            let mut proxy = proxy.clone();
+            if !proxy.username().is_empty() {
+                let _ = proxy.set_username("***");
+            }
+            if proxy.password().is_some() {
+                let _ = proxy.set_password(Some("***"));
+            }
+            proxy.to_string()
+        });
+        f.debug_struct("HttpOptions")
+            .field("timeout_secs", &self.timeout_secs)
+            .field("proxy", &proxy)
+            .field("no_proxy", &self.no_proxy)
+            .field("user_agent", &self.user_agent)
+            .field("insecure_tls", &self.insecure_tls)
+            .field("ca_cert", &self.ca_cert)
+            .finish()
+    }
+}
+
+/// A root certificate read from `ca_cert` when the config is loaded.
+#[derive(Clone)]
+pub struct CaCert {
+    pub path: PathBuf,
+    pem: Vec<u8>,
+}
+
+impl Debug for CaCert {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CaCert").field(&self.path).finish()
+    }
+}
+
+impl CaCert {
+    /// Read and checked up front like the proxy, so building a client later can't fail on it.
+    pub fn load(path: PathBuf) -> Result<CaCert, String> {
+        let pem = std::fs::read(&path)
+            .map_err(|e| format!("Failed to read CA certificate {}: {}", path.display(), e))?;
+        Certificate::from_pem(&pem)
+            .map_err(|e| format!("Invalid CA certificate {}: {}", path.display(), e))?;
+        Ok(CaCert { path, pem })
+    }
+
+    fn certificate(&self) -> Certificate {
+        Certificate::from_pem(&self.pem).expect("CA certificate was already checked")
+    }
+}
+
+fn deserialize_ca_cert<'de, D>(deserializer: D) -> Result<Option<CaCert>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<PathBuf>::deserialize(deserializer)?
+        .map(|path| CaCert::load(path).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// Checked up front, so building a client later can't fail on it.
+pub fn parse_proxy(proxy: &str) -> Result<Url, String> {
+    let url = Url::parse(proxy).map_err(|e| format!("Invalid proxy URL {:?}: {}", proxy, e))?;
+    match url.scheme() {
+        "http" | "https" | "socks5" | "socks5h" => Ok(url),
+        scheme => Err(format!(
+            "Unsupported proxy scheme {:?}, expected http, https, socks5 or socks5h",
+            scheme
+        )),
+    }
+}
+
+fn deserialize_proxy<'de, D>(deserializer: D) -> Result<Option<Url>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|proxy| parse_proxy(&proxy).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// Checked up front like the proxy, so building a client later can't fail on it.
+fn deserialize_user_agent<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|user_agent| match HeaderValue::from_str(&user_agent) {
+            Ok(_) => Ok(user_agent),
+            Err(_) => Err(D::Error::custom(format!(
+                "Invalid user_agent {:?}, it can't be sent as a header",
+                user_agent
+            ))),
+        })
+        .transpose()
+}
+
+impl HttpOptions {
+    /// Takes any setting not given here from `defaults`.
+    pub fn inherit(&mut self, defaults: &HttpOptions) {
+        self.timeout_secs = self.timeout_secs.or(defaults.timeout_secs);
+        self.user_agent = self
+            .user_agent
+            .take()
+            .or_else(|| defaults.user_agent.clone());
+        if self.proxy.is_none() {
+            self.proxy = defaults.proxy.clone();
+            self.no_proxy = self.no_proxy.take().or_else(|| defaults.no_proxy.clone());
+        }
+        self.insecure_tls = self.insecure_tls.or(defaults.insecure_tls);
+        self.ca_cert = self.ca_cert.take().or_else(|| defaults.ca_cert.clone());
+    }
+
+    pub(crate) fn client(&self) -> Client {
+        self.client_builder()
+            .build()
+            .expect("Failed to build HTTP client")
+    }
+
+    /// For callers that need more than these settings, like binding to one address family.
+    pub(crate) fn client_builder(&self) -> ClientBuilder {
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(
+                self.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS),
+            ))
+            .user_agent(self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT));
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy.clone())
+                .expect("Proxy URL was already checked")
+                .no_proxy(self.no_proxy.as_deref().and_then(NoProxy::from_string));
+            builder = builder.proxy(proxy);
+        }
+        if let Some(ca_cert) = &self.ca_cert {
+            builder = builder.add_root_certificate(ca_cert.certificate());
+        }
+        if self.insecure_tls == Some(true) {
+            warn!("insecure_tls is set, TLS certificates will NOT be verified and requests can be intercepted");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        builder
+    }
+}
+
+/// Sends requests for providers, so they can be tested against a mock instead of the real API.
+#[async_trait]
+pub(crate) trait HttpClient: Send + Sync {
+    async fn send(&self, request: &HttpRequest) -> Result<HttpResponse, HttpError>;
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl HttpRequest {
+    pub(crate) fn new(method: Method, url: impl Into<String>) -> HttpRequest {
+        HttpRequest {
+            method,
+            url: url.into(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub(crate) fn get(url: impl Into<String>) -> HttpRequest {
+        HttpRequest::new(Method::GET, url)
+    }
+
+    pub(crate) fn put(url: impl Into<String>) -> HttpRequest {
+        HttpRequest::new(Method::PUT, url)
+    }
+
+    pub(crate) fn post(url: impl Into<String>) -> HttpRequest {
+        HttpRequest::new(Method::POST, url)
+    }
+
+    pub(crate) fn delete(url: impl Into<String>) -> HttpRequest {
+        HttpRequest::new(Method::DELETE, url)
+    }
+
+    pub(crate) fn query(mut self, pairs: &[(&str, &str)]) -> HttpRequest {
+        self.query.extend(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+        self
+    }
+
+    pub(crate) fn header(mut self, name: &str, value: impl Into<String>) -> HttpRequest {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub(crate) fn bearer_auth(self, token: &str) -> HttpRequest {
+        self.header("Authorization", format!("Bearer {}", token))
+    }
+
+    pub(crate) fn json<T: Serialize>(self, body: &T) -> color_eyre::Result<HttpRequest> {
+        let mut request = self.header("Content-Type", "application/json");
+        request.body = Some(serde_json::to_vec(body).wrap_err("Failed to serialize body")?);
+        Ok(request)
+    }
+}
+
+/// A response with its body already read.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpResponse {
+    pub status: StatusCode,
+    /// How long the server asked to wait before trying again, from a `Retry-After` in seconds.
+    pub retry_after: Option<Duration>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub(crate) fn json<T: DeserializeOwned>(&self) -> color_eyre::Result<T> {
+        serde_json::from_slice(&self.body).wrap_err("Failed to read response")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HttpErrorKind {
+    Timeout,
+    Connect,
+    Other,
+}
+
+/// No response was received, `kind` says whether trying again might help.
+#[derive(Debug)]
+pub(crate) struct HttpError {
+    pub kind: HttpErrorKind,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl HttpError {
+    pub(crate) fn new(
+        kind: HttpErrorKind,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> HttpError {
+        HttpError {
+            kind,
+            source: source.into(),
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            HttpErrorKind::Timeout => write!(f, "Request timed out"),
+            HttpErrorKind::Connect => write!(f, "Failed to connect"),
+            HttpErrorKind::Other => write!(f, "Failed to send request"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(e: reqwest::Error) -> HttpError {
+        let kind = if e.is_timeout() {
+            HttpErrorKind::Timeout
+        } else if e.is_connect() {
+            HttpErrorKind::Connect
+        } else {
+            HttpErrorKind::Other
+        };
+        HttpError::new(kind, e)
+    }
+}
+
+#[async_trait]
+impl HttpClient for Client {
+    async fn send(&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+        let mut builder = self
+            .request(request.method.clone(), &request.url)
+            .query(&request.query);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
+        }
+        let response = builder.send().await?;
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        Ok(HttpResponse {
+            status: response.status(),
+            retry_after,
+            body: response.bytes().await?.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Answers with canned responses in order and records every request it was given.
+    #[derive(Default)]
+    pub(crate) struct MockHttpClient {
+        responses: Mutex<VecDeque<HttpResponse>>,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl MockHttpClient {
+        pub(crate) fn respond(self, status: u16, body: &str) -> MockHttpClient {
+            self.responses.lock().unwrap().push_back(HttpResponse {
+                status: StatusCode::from_u16(status).unwrap(),
+                retry_after: None,
+                body: body.as_bytes().to_vec(),
+            });
+            self
+        }
+
+        pub(crate) fn respond_rate_limited(self, retry_after_secs: u64) -> MockHttpClient {
+            self.responses.lock().unwrap().push_back(HttpResponse {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                retry_after: Some(Duration::from_secs(retry_after_secs)),
+                body: br#"{"success": false, "errors": [], "result": null}"#.to_vec(),
+            });
+            self
+        }
+
+        pub(crate) fn requests(&self) -> Vec<HttpRequest> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    /// So a test can keep hold of the mock after handing it to a provider.
+    #[async_trait]
+    impl HttpClient for std::sync::Arc<MockHttpClient> {
+        async fn send(&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+            (**self).send(request).await
+        }
+    }
+
+    #[async_trait]
+    impl HttpClient for MockHttpClient {
+        async fn send(&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+            self.requests.lock().unwrap().push(request.clone());
+            self.responses.lock().unwrap().pop_front().ok_or_else(|| {
+                HttpError::new(
+                    HttpErrorKind::Other,
+                    format!("No response left for {} {}", request.method, request.url),
+                )
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Sends one request to a local stub and returns the User-Agent it arrived with.
+    async fn user_agent_sent(options: HttpOptions) -> Option<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let stub = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut user_agent = None;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("user-agent") {
+                        user_agent = Some(value.trim().to_string());
+                    }
+                }
+                line.clear();
+            }
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            user_agent
+        });
+
+        options.client().get(&url).send().await.unwrap();
+
+        stub.join().unwrap()
+    }
+
+    #[tokio::test]
+    async fn default_user_agent_is_sent() {
+        assert_eq!(
+            user_agent_sent(HttpOptions::default()).await.as_deref(),
+            Some(DEFAULT_USER_AGENT)
+        );
+    }
+
+    #[tokio::test]
+    async fn user_agent_can_be_overridden() {
+        let options = HttpOptions {
+            user_agent: Some("my-agent/1.0".to_string()),
+            ..HttpOptions::default()
+        };
+
+        assert_eq!(
+            user_agent_sent(options).await.as_deref(),
+            Some("my-agent/1.0")
+        );
+    }
+
+    #[test]
+    fn ca_cert_is_checked_when_loaded() {
+        let error = serde_json::from_value::<HttpOptions>(serde_json::json!({
+            "ca_cert": "/nonexistent/ca.pem",
+        }))
+        .unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .starts_with("Failed to read CA certificate /nonexistent/ca.pem"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn user_agent_is_checked_when_loaded() {
+        let error = serde_json::from_value::<HttpOptions>(serde_json::json!({
+            "user_agent": "my-agent/1.0\r\nX-Injected: 1",
+        }))
+        .unwrap_err();
+
+        assert!(
+            error.to_string().starts_with("Invalid user_agent"),
+            "{}",
+            error
+        );
+    }
+}
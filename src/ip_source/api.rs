@@ -0,0 +1,12 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait IpSource: Send + Sync {
+    fn describe(&self) -> String;
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr>;
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr>;
+}
@@ -0,0 +1,168 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, ContextCompat, WrapErr};
+use serde::Deserialize;
+use trust_dns_resolver::proto::op::{Message, MessageType, OpCode, Query};
+use trust_dns_resolver::proto::rr::{Name, RData, RecordType};
+
+use crate::http::HttpOptions;
+use crate::ip_source::api::IpSource;
+
+const MYIP: &str = "myip.opendns.com.";
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// Looks up `myip.opendns.com` like [OpenDns](crate::ip_source::opendns::OpenDns), but over
+/// DNS-over-HTTPS, for networks that block or intercept plain DNS.
+pub struct Doh {
+    config: DohConfig,
+    ipv4_client: reqwest::Client,
+    ipv6_client: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DohConfig {
+    /// Has to be OpenDNS's, as only its resolvers answer `myip.opendns.com` with the caller's
+    /// address.
+    #[serde(default = "default_url")]
+    pub url: String,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Default for DohConfig {
+    fn default() -> Self {
+        DohConfig {
+            url: default_url(),
+            http: HttpOptions::default(),
+        }
+    }
+}
+
+fn default_url() -> String {
+    "https://doh.opendns.com/dns-query".to_string()
+}
+
+impl Doh {
+    pub fn from_config(config: DohConfig) -> Self {
+        // The family the request is made over decides which address comes back
+        let client = |local: IpAddr| {
+            config
+                .http
+                .client_builder()
+                .local_address(local)
+                .build()
+                .expect("Failed to build HTTP client")
+        };
+        Doh {
+            ipv4_client: client(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            ipv6_client: client(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            config,
+        }
+    }
+
+    async fn lookup(
+        &self,
+        client: &reqwest::Client,
+        record_type: RecordType,
+    ) -> color_eyre::Result<Message> {
+        let mut query = Message::new();
+        // RFC 8484 asks for id 0, so answers can be cached
+        query
+            .set_id(0)
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .set_recursion_desired(true)
+            .add_query(Query::query(
+                Name::from_ascii(MYIP).expect("Name is valid"),
+                record_type,
+            ));
+        let body = query.to_vec().wrap_err("Failed to encode DNS query")?;
+
+        let response = client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, DNS_MESSAGE)
+            .header(reqwest::header::ACCEPT, DNS_MESSAGE)
+            .body(body)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(eyre!(
+                "{} Error from {}",
+                response.status(),
+                self.config.url
+            ));
+        }
+        let body = response.bytes().await.wrap_err("Failed to read response")?;
+        Message::from_vec(&body)
+            .wrap_err_with(|| format!("Invalid DNS response from {}", self.config.url))
+    }
+}
+
+fn first_answer<A>(message: &Message, extract: fn(&RData) -> Option<A>) -> Option<A> {
+    message
+        .answers()
+        .iter()
+        .find_map(|record| extract(record.rdata()))
+}
+
+#[async_trait]
+impl IpSource for Doh {
+    fn describe(&self) -> String {
+        format!("OpenDNS over HTTPS[{url}]", url = &self.config.url)
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        let response = self.lookup(&self.ipv4_client, RecordType::A).await?;
+        first_answer(&response, |rdata| match rdata {
+            RData::A(address) => Some(*address),
+            _ => None,
+        })
+        .wrap_err("No IPv4 addresses returned")
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        let response = self.lookup(&self.ipv6_client, RecordType::AAAA).await?;
+        first_answer(&response, |rdata| match rdata {
+            RData::AAAA(address) => Some(*address),
+            _ => None,
+        })
+        .wrap_err("No IPv6 addresses returned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use trust_dns_resolver::proto::rr::Record;
+
+    use super::*;
+
+    #[test]
+    fn address_is_read_from_the_answers() {
+        let mut response = Message::new();
+        response
+            .set_message_type(MessageType::Response)
+            .add_answer(Record::from_rdata(
+                Name::from_ascii(MYIP).unwrap(),
+                0,
+                RData::A(Ipv4Addr::new(203, 0, 113, 5)),
+            ));
+        let response = Message::from_vec(&response.to_vec().unwrap()).unwrap();
+
+        assert_eq!(
+            first_answer(&response, |rdata| match rdata {
+                RData::A(address) => Some(*address),
+                _ => None,
+            }),
+            Some(Ipv4Addr::new(203, 0, 113, 5))
+        );
+        assert_eq!(
+            first_answer(&response, |rdata| match rdata {
+                RData::AAAA(address) => Some(*address),
+                _ => None,
+            }),
+            None
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+
+use crate::ip_source::api::IpSource;
+use crate::ip_source::single_server_resolver;
+
+const MYADDR: &str = "o-o.myaddr.l.google.com.";
+
+/// Looks up the `o-o.myaddr.l.google.com` TXT record against Google's authoritative servers.
+pub struct Google;
+
+impl Google {
+    async fn lookup<A: FromStr>(server: IpAddr) -> color_eyre::Result<A> {
+        let resolver = single_server_resolver(server)?;
+        let response = resolver
+            .txt_lookup(MYADDR)
+            .await
+            .wrap_err("Failed to resolve TXT record")?;
+        let values: Vec<String> = response.iter().map(ToString::to_string).collect();
+        values
+            .iter()
+            .find_map(|value| value.parse().ok())
+            .ok_or_else(|| eyre!("No address in TXT records {:?}", values))
+    }
+}
+
+#[async_trait]
+impl IpSource for Google {
+    fn describe(&self) -> String {
+        "Google".to_string()
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        // ns1.google.com
+        Google::lookup(IpAddr::V4(Ipv4Addr::new(216, 239, 32, 10))).await
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        // ns1.google.com
+        Google::lookup(IpAddr::V6(Ipv6Addr::new(
+            0x2001, 0x4860, 0x4802, 0x32, 0, 0, 0, 0xa,
+        )))
+        .await
+    }
+}
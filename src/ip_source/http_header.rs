@@ -0,0 +1,121 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use serde::Deserialize;
+
+use crate::http::HttpOptions;
+use crate::ip_source::api::IpSource;
+
+/// Fetches a URL and reads the caller's address from a response header, for when a trusted
+/// upstream such as a reverse proxy reports it that way rather than in the body.
+pub struct HttpHeader {
+    config: HttpHeaderConfig,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HttpHeaderConfig {
+    pub url: String,
+    /// Fetched for the IPv6 address instead, if the upstream needs a separate name to be reached
+    /// over IPv6. Defaults to `url`.
+    pub ipv6_url: Option<String>,
+    #[serde(default = "default_header")]
+    pub header: String,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_header() -> String {
+    "X-Real-IP".to_string()
+}
+
+impl HttpHeader {
+    pub fn from_config(config: HttpHeaderConfig) -> Self {
+        HttpHeader {
+            client: config.http.client(),
+            config,
+        }
+    }
+
+    async fn fetch<A>(&self, url: &str) -> color_eyre::Result<A>
+    where
+        A: FromStr,
+        A::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(eyre!("{} Error from {}", response.status(), url));
+        }
+        let value = response
+            .headers()
+            .get(&self.config.header)
+            .ok_or_else(|| eyre!("No {} header from {}", self.config.header, url))?
+            .to_str()
+            .wrap_err_with(|| format!("Invalid {} header from {}", self.config.header, url))?;
+        parse_header(value)
+            .wrap_err_with(|| format!("Invalid {} header from {}", self.config.header, url))
+    }
+}
+
+/// Headers like `X-Forwarded-For` list every hop, and the first is the original client.
+fn parse_header<A>(value: &str) -> color_eyre::Result<A>
+where
+    A: FromStr,
+    A::Err: std::error::Error + Send + Sync + 'static,
+{
+    let first = value.split(',').next().unwrap_or_default().trim();
+    first
+        .parse()
+        .wrap_err_with(|| format!("Invalid address: {:?}", value))
+}
+
+#[async_trait]
+impl IpSource for HttpHeader {
+    fn describe(&self) -> String {
+        format!(
+            "HTTP header[{header} from {url}]",
+            header = &self.config.header,
+            url = &self.config.url,
+        )
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        self.fetch(&self.config.url).await
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        self.fetch(self.config.ipv6_url.as_deref().unwrap_or(&self.config.url))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_must_hold_an_address_of_the_family() {
+        assert_eq!(
+            parse_header::<Ipv4Addr>("203.0.113.5").unwrap(),
+            Ipv4Addr::new(203, 0, 113, 5)
+        );
+        assert_eq!(
+            parse_header::<Ipv4Addr>(" 203.0.113.5, 10.0.0.1").unwrap(),
+            Ipv4Addr::new(203, 0, 113, 5)
+        );
+        assert_eq!(
+            parse_header::<Ipv6Addr>("2001:db8::1").unwrap(),
+            "2001:db8::1".parse::<Ipv6Addr>().unwrap()
+        );
+        assert!(parse_header::<Ipv4Addr>("2001:db8::1").is_err());
+        assert!(parse_header::<Ipv4Addr>("unknown").is_err());
+        assert!(parse_header::<Ipv4Addr>("").is_err());
+    }
+}
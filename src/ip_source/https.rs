@@ -0,0 +1,92 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use serde::Deserialize;
+
+use crate::http::HttpOptions;
+use crate::ip_source::api::IpSource;
+
+/// Fetches a URL that responds with the caller's address as a bare string.
+pub struct Https {
+    config: HttpsConfig,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HttpsConfig {
+    #[serde(default = "default_ipv4_url")]
+    pub ipv4_url: String,
+    #[serde(default = "default_ipv6_url")]
+    pub ipv6_url: String,
+    /// Settings for the requests, so the address can be found through a proxy.
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Default for HttpsConfig {
+    fn default() -> Self {
+        HttpsConfig {
+            ipv4_url: default_ipv4_url(),
+            ipv6_url: default_ipv6_url(),
+            http: HttpOptions::default(),
+        }
+    }
+}
+
+fn default_ipv4_url() -> String {
+    "https://api.ipify.org".to_string()
+}
+
+fn default_ipv6_url() -> String {
+    "https://api6.ipify.org".to_string()
+}
+
+impl Https {
+    pub fn from_config(config: HttpsConfig) -> Self {
+        Https {
+            client: config.http.client(),
+            config,
+        }
+    }
+
+    async fn fetch<A>(&self, url: &str) -> color_eyre::Result<A>
+    where
+        A: FromStr,
+        A::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(eyre!("{} Error from {}", response.status(), url));
+        }
+        let body = response.text().await.wrap_err("Failed to read response")?;
+        body.trim()
+            .parse()
+            .wrap_err_with(|| format!("Invalid address from {}: {:?}", url, body))
+    }
+}
+
+#[async_trait]
+impl IpSource for Https {
+    fn describe(&self) -> String {
+        format!(
+            "HTTPS[{ipv4_url}, {ipv6_url}]",
+            ipv4_url = &self.config.ipv4_url,
+            ipv6_url = &self.config.ipv6_url,
+        )
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        self.fetch(&self.config.ipv4_url).await
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        self.fetch(&self.config.ipv6_url).await
+    }
+}
@@ -0,0 +1,59 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+
+use crate::ip_source::api::IpSource;
+use crate::ip_source::check_globally_routable;
+
+/// Reads the address straight off a local network interface, for machines that hold their public
+/// IP themselves. Nothing leaves the machine.
+pub struct Interface {
+    name: String,
+}
+
+impl Interface {
+    pub fn new(name: String) -> Self {
+        Interface { name }
+    }
+
+    /// The first globally routable address on the interface that `pick` accepts.
+    fn find<A>(&self, pick: impl Fn(IpAddr) -> Option<A>) -> color_eyre::Result<Option<A>> {
+        let addresses: Vec<IpAddr> = if_addrs::get_if_addrs()
+            .wrap_err("Failed to list network interfaces")?
+            .into_iter()
+            .filter(|interface| interface.name == self.name)
+            .map(|interface| interface.ip())
+            .collect();
+        if addresses.is_empty() {
+            return Err(eyre!("No network interface named {}", self.name));
+        }
+        Ok(addresses
+            .into_iter()
+            .filter(|ip| check_globally_routable(*ip).is_ok())
+            .find_map(pick))
+    }
+}
+
+#[async_trait]
+impl IpSource for Interface {
+    fn describe(&self) -> String {
+        format!("Interface[{name}]", name = &self.name)
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        self.find(|ip| match ip {
+            IpAddr::V4(v4) => Some(v4),
+            IpAddr::V6(_) => None,
+        })?
+        .ok_or_else(|| eyre!("Interface {} has no public IPv4 address", self.name))
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        self.find(|ip| match ip {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(v6) => Some(v6),
+        })?
+        .ok_or_else(|| eyre!("Interface {} has no public IPv6 address", self.name))
+    }
+}
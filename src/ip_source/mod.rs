@@ -0,0 +1,84 @@
+use std::net::IpAddr;
+
+use color_eyre::eyre::{eyre, WrapErr};
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::TokioAsyncResolver;
+
+pub mod api;
+pub mod doh;
+pub mod google;
+pub mod http_header;
+pub mod https;
+pub mod interface;
+pub mod multi;
+pub mod opendns;
+pub mod per_family;
+
+/// Builds a resolver that talks only to the given server. The "what's my IP" DNS services answer
+/// with the address the query came from, so the server's family decides which address we learn.
+fn single_server_resolver(server: IpAddr) -> color_eyre::Result<TokioAsyncResolver> {
+    resolver(&[server])
+}
+
+/// Like [single_server_resolver], but falls back to the later servers when earlier ones fail. They
+/// should all be the same family.
+pub(crate) fn resolver(servers: &[IpAddr]) -> color_eyre::Result<TokioAsyncResolver> {
+    TokioAsyncResolver::tokio(resolver_config(servers), ResolverOpts::default())
+        .wrap_err("Failed to initialize resolver")
+}
+
+fn resolver_config(servers: &[IpAddr]) -> ResolverConfig {
+    ResolverConfig::from_parts(
+        None,
+        vec![],
+        NameServerConfigGroup::from_ips_clear(servers, 53, true),
+    )
+}
+
+/// Rejects addresses that can't be reached from the internet, which a misbehaving source could
+/// hand back (e.g. a DNS resolver answering with a LAN address).
+pub fn check_globally_routable(ip: IpAddr) -> color_eyre::Result<()> {
+    let kind = match ip {
+        IpAddr::V4(v4) if v4.is_unspecified() => "unspecified",
+        IpAddr::V4(v4) if v4.is_loopback() => "loopback",
+        IpAddr::V4(v4) if v4.is_private() => "private",
+        IpAddr::V4(v4) if v4.is_link_local() => "link-local",
+        // 100.64.0.0/10, the carrier-grade NAT shared address space
+        IpAddr::V4(v4) if v4.octets()[0] == 100 && v4.octets()[1] & 0xc0 == 64 => "CGNAT",
+        IpAddr::V6(v6) if v6.is_unspecified() => "unspecified",
+        IpAddr::V6(v6) if v6.is_loopback() => "loopback",
+        IpAddr::V6(v6) if v6.is_unique_local() => "private",
+        IpAddr::V6(v6) if v6.is_unicast_link_local() => "link-local",
+        _ => return Ok(()),
+    };
+    Err(eyre!(
+        "{} is a {} address, not a public one. Use --allow-private if this is intended",
+        ip,
+        kind
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn resolver_uses_every_configured_server() {
+        let servers = [
+            IpAddr::V4(Ipv4Addr::new(208, 67, 222, 222)),
+            IpAddr::V4(Ipv4Addr::new(208, 67, 220, 220)),
+        ];
+
+        let config = resolver_config(&servers);
+        let mut used: Vec<IpAddr> = config
+            .name_servers()
+            .iter()
+            .map(|name_server| name_server.socket_addr.ip())
+            .collect();
+        used.dedup();
+
+        assert_eq!(used, servers);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use futures_util::future::join_all;
+use log::warn;
+use serde::Deserialize;
+
+use crate::ip_source::api::IpSource;
+
+/// Asks several sources, so one giving a stale or wrong address doesn't end up in the records.
+pub struct Multi {
+    sources: Vec<Box<dyn IpSource>>,
+    strategy: Strategy,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// The sources are asked in order, and the first to answer is used.
+    #[default]
+    First,
+    /// Every source is asked, and more than half of them must give the same address.
+    Majority,
+}
+
+impl Multi {
+    pub fn new(sources: Vec<Box<dyn IpSource>>, strategy: Strategy) -> Self {
+        Multi { sources, strategy }
+    }
+
+    async fn pick<A, F>(&self, answers: impl Iterator<Item = (String, F)>) -> color_eyre::Result<A>
+    where
+        A: PartialEq + Copy + Display,
+        F: Future<Output = color_eyre::Result<A>>,
+    {
+        match self.strategy {
+            Strategy::First => first(answers).await,
+            Strategy::Majority => {
+                let (names, futures): (Vec<_>, Vec<_>) = answers.unzip();
+                majority(names.into_iter().zip(join_all(futures).await).collect())
+            }
+        }
+    }
+}
+
+async fn first<A, F>(answers: impl Iterator<Item = (String, F)>) -> color_eyre::Result<A>
+where
+    F: Future<Output = color_eyre::Result<A>>,
+{
+    let mut failed = Vec::new();
+    for (name, answer) in answers {
+        match answer.await {
+            Ok(ip) => return Ok(ip),
+            Err(e) => {
+                warn!("{} failed, trying the next source: {:#}", name, e);
+                failed.push(format!("{}: {:#}", name, e));
+            }
+        }
+    }
+    Err(eyre!("Every IP source failed: {}", failed.join("; ")))
+}
+
+/// Failed sources count against the majority, so two of three must agree even if the third
+/// gave nothing.
+fn majority<A>(answers: Vec<(String, color_eyre::Result<A>)>) -> color_eyre::Result<A>
+where
+    A: PartialEq + Copy + Display,
+{
+    let mut counts: Vec<(A, usize)> = Vec::new();
+    for ip in answers
+        .iter()
+        .filter_map(|(_, answer)| answer.as_ref().ok())
+    {
+        match counts.iter_mut().find(|(counted, _)| counted == ip) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((*ip, 1)),
+        }
+    }
+    if let Some((ip, _)) = counts.iter().find(|(_, count)| count * 2 > answers.len()) {
+        return Ok(*ip);
+    }
+    Err(eyre!(
+        "IP sources don't agree on an address: {}",
+        answers
+            .iter()
+            .map(|(name, answer)| match answer {
+                Ok(ip) => format!("{} said {}", name, ip),
+                Err(e) => format!("{} failed ({:#})", name, e),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+#[async_trait]
+impl IpSource for Multi {
+    fn describe(&self) -> String {
+        let sources = self
+            .sources
+            .iter()
+            .map(|source| source.describe())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.strategy {
+            Strategy::First => format!("the first to answer of {}", sources),
+            Strategy::Majority => format!("the majority of {}", sources),
+        }
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        self.pick(
+            self.sources
+                .iter()
+                .map(|source| (source.describe(), source.public_ipv4())),
+        )
+        .await
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        self.pick(
+            self.sources
+                .iter()
+                .map(|source| (source.describe(), source.public_ipv6())),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn majority_needs_more_than_half_to_agree() {
+        let ip = |s: &str| -> color_eyre::Result<Ipv4Addr> { Ok(s.parse().unwrap()) };
+        let answers = |results: Vec<color_eyre::Result<Ipv4Addr>>| {
+            ["opendns", "ipify", "icanhazip"]
+                .iter()
+                .map(ToString::to_string)
+                .zip(results)
+                .collect()
+        };
+
+        assert_eq!(
+            majority(answers(vec![
+                ip("203.0.113.5"),
+                ip("198.51.100.1"),
+                ip("203.0.113.5")
+            ]))
+            .unwrap(),
+            "203.0.113.5".parse::<Ipv4Addr>().unwrap()
+        );
+        assert_eq!(
+            majority(answers(vec![
+                ip("203.0.113.5"),
+                Err(eyre!("timed out")),
+                ip("198.51.100.1")
+            ]))
+            .unwrap_err()
+            .to_string(),
+            "IP sources don't agree on an address: opendns said 203.0.113.5, \
+             ipify failed (timed out), icanhazip said 198.51.100.1"
+        );
+    }
+}
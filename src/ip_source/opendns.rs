@@ -0,0 +1,98 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, ContextCompat, WrapErr};
+use serde::Deserialize;
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::ip_source::api::IpSource;
+use crate::ip_source::resolver;
+
+const MYIP: &str = "myip.opendns.com.";
+
+/// Looks up `myip.opendns.com` against OpenDNS's own resolvers.
+pub struct OpenDns {
+    config: OpenDnsConfig,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OpenDnsConfig {
+    /// Tried in order. The IPv4 ones are used to find the IPv4 address and likewise for IPv6.
+    #[serde(default = "default_servers")]
+    pub servers: Vec<IpAddr>,
+}
+
+impl Default for OpenDnsConfig {
+    fn default() -> Self {
+        OpenDnsConfig {
+            servers: default_servers(),
+        }
+    }
+}
+
+fn default_servers() -> Vec<IpAddr> {
+    vec![
+        IpAddr::V4(Ipv4Addr::new(208, 67, 222, 222)),
+        IpAddr::V4(Ipv4Addr::new(208, 67, 220, 220)),
+        IpAddr::V6(Ipv6Addr::new(0x2620, 0x119, 0x35, 0, 0, 0, 0, 0x35)),
+        IpAddr::V6(Ipv6Addr::new(0x2620, 0x119, 0x53, 0, 0, 0, 0, 0x53)),
+    ]
+}
+
+impl OpenDns {
+    pub fn from_config(config: OpenDnsConfig) -> Self {
+        OpenDns { config }
+    }
+
+    fn resolver(&self, ipv4: bool) -> color_eyre::Result<TokioAsyncResolver> {
+        let servers: Vec<IpAddr> = self
+            .config
+            .servers
+            .iter()
+            .copied()
+            .filter(|server| server.is_ipv4() == ipv4)
+            .collect();
+        if servers.is_empty() {
+            return Err(eyre!(
+                "No {} OpenDNS servers configured",
+                if ipv4 { "IPv4" } else { "IPv6" }
+            ));
+        }
+        resolver(&servers)
+    }
+}
+
+#[async_trait]
+impl IpSource for OpenDns {
+    fn describe(&self) -> String {
+        "OpenDNS".to_string()
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        let response = self
+            .resolver(true)?
+            .ipv4_lookup(MYIP)
+            .await
+            .wrap_err("Failed to resolve IPv4 address")?;
+        let address = response
+            .iter()
+            .next()
+            .copied()
+            .wrap_err("No IPv4 addresses returned")?;
+        Ok(address)
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        let response = self
+            .resolver(false)?
+            .ipv6_lookup(MYIP)
+            .await
+            .wrap_err("Failed to resolve IPv6 address")?;
+        let address = response
+            .iter()
+            .next()
+            .copied()
+            .wrap_err("No IPv6 addresses returned")?;
+        Ok(address)
+    }
+}
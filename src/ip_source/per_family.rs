@@ -0,0 +1,37 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+
+use crate::ip_source::api::IpSource;
+
+/// Asks one source for the IPv4 address and another for the IPv6 one, e.g. OpenDNS for IPv4 and an
+/// IPv6-only HTTPS endpoint for IPv6.
+pub struct PerFamily {
+    v4: Box<dyn IpSource>,
+    v6: Box<dyn IpSource>,
+}
+
+impl PerFamily {
+    pub fn new(v4: Box<dyn IpSource>, v6: Box<dyn IpSource>) -> Self {
+        PerFamily { v4, v6 }
+    }
+}
+
+#[async_trait]
+impl IpSource for PerFamily {
+    fn describe(&self) -> String {
+        format!(
+            "{v4} for IPv4 and {v6} for IPv6",
+            v4 = self.v4.describe(),
+            v6 = self.v6.describe(),
+        )
+    }
+
+    async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+        self.v4.public_ipv4().await
+    }
+
+    async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+        self.v6.public_ipv6().await
+    }
+}
@@ -0,0 +1,926 @@
+//! Updates DNS records to point at the current public IP. The `box-dyn-dns` binary is a thin
+//! command line wrapper around [run_once]; [run] is for callers that already know the address.
+
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use color_eyre::eyre::eyre;
+use futures_util::future::join_all;
+use log::{debug, error, info, warn};
+use tokio::sync::Semaphore;
+
+pub use crate::cache::default_cache_path;
+use crate::cache::IpCache;
+use crate::config::{Family, ProviderConfig, Secrets};
+use crate::history::HistoryEntry;
+pub use crate::history::HistoryFile;
+use crate::ip_source::api::IpSource;
+use crate::notify::{IpChange, Notifier};
+use crate::propagation::PropagationConfig;
+use crate::summary::SummaryEntry;
+pub use crate::summary::SummaryFormat;
+pub use crate::update_dns::api::{
+    ModifiedOn, RecordType, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome,
+    UpdateResult,
+};
+
+mod cache;
+pub mod config;
+mod env;
+mod history;
+pub mod http;
+pub mod ip_source;
+pub mod notify;
+pub mod propagation;
+mod summary;
+pub mod update_dns;
+
+/// How [run_once] treats the cache and the addresses it finds.
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    /// Update records even if the cache says they already have the current IP.
+    pub force: bool,
+    /// Only log the changes that would be made, without making them.
+    pub dry_run: bool,
+    /// Where to remember the last applied IPs, `None` to not remember them.
+    pub cache_file: Option<PathBuf>,
+    /// Accept private, loopback, link-local and CGNAT addresses as the public IP.
+    pub allow_private: bool,
+    /// How long to wait between one record update and the next, to stay under rate limits.
+    pub stagger: Duration,
+    /// Give up on a run that takes longer than this in total, lookup and updates together.
+    pub deadline: Option<Duration>,
+    /// Where to log each record that gets a new address, if anywhere.
+    pub history: Option<HistoryFile>,
+    /// How many provider requests may be in flight at once. 0 is taken as 1, one at a time.
+    pub concurrency: usize,
+    /// How to report what happened to each record at the end of the run.
+    pub summary_format: SummaryFormat,
+    /// Don't give a name a new address until this long after it last got one, so an address that
+    /// keeps bouncing doesn't mean an update on every run. Needs the cache file to remember when.
+    pub min_change_interval: Option<Duration>,
+}
+
+/// An updater along with the address families it should be given.
+pub struct Provider {
+    pub dns: Box<dyn UpdateDns>,
+    pub families: Vec<Family>,
+    /// How to check that new addresses are visible, `None` to not check.
+    pub verify: Option<PropagationConfig>,
+}
+
+impl Provider {
+    pub fn handles(&self, ip: IpAddr) -> bool {
+        self.families.contains(&match ip {
+            IpAddr::V4(_) => Family::V4,
+            IpAddr::V6(_) => Family::V6,
+        })
+    }
+}
+
+impl From<ProviderConfig> for Provider {
+    fn from(config: ProviderConfig) -> Provider {
+        Provider {
+            dns: config.update_dns.into(),
+            families: config.families,
+            verify: if config.verify_after_update {
+                Some(config.propagation)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Asks every provider to check its settings, reporting each rather than stopping at the first
+/// failure.
+pub async fn run_check(update_dns: &[Provider]) -> color_eyre::Result<()> {
+    let mut failed = 0;
+    for provider in update_dns {
+        match provider.dns.check().await {
+            Some(Ok(())) => info!("{}: OK", provider.dns.describe()),
+            Some(Err(e)) => {
+                error!("{}: {:?}", provider.dns.describe(), e);
+                failed += 1;
+            }
+            None => info!(
+                "{}: config OK, credentials can't be checked without updating",
+                provider.dns.describe()
+            ),
+        }
+    }
+    if failed > 0 {
+        return Err(eyre!(
+            "{} of {} providers failed the check",
+            failed,
+            update_dns.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Resolves the public IP and sends it to every provider for every name, carrying on past failures
+/// so one broken provider doesn't hold back the others.
+pub async fn run_once(
+    options: &RunOptions,
+    ip_source: &dyn IpSource,
+    update_dns: &[Provider],
+    notifier: Option<&Notifier>,
+    names: &[String],
+) -> color_eyre::Result<Vec<UpdateOutcome>> {
+    with_deadline(options, async {
+        let addresses = resolve_addresses(options, ip_source).await?;
+        update_all(options, &addresses, update_dns, notifier, names).await
+    })
+    .await
+}
+
+/// Like [run_once], but with the addresses given instead of looked up, at most one of each family.
+/// They're checked the same way, so a private one needs [RunOptions::allow_private].
+pub async fn run_with_addresses(
+    options: &RunOptions,
+    addresses: &[IpAddr],
+    update_dns: &[Provider],
+    notifier: Option<&Notifier>,
+    names: &[String],
+) -> color_eyre::Result<Vec<UpdateOutcome>> {
+    for (index, address) in addresses.iter().enumerate() {
+        if let Some(earlier) = addresses[..index]
+            .iter()
+            .find(|earlier| earlier.is_ipv4() == address.is_ipv4())
+        {
+            return Err(eyre!(
+                "Only one address of each family can be given, got {} after {}",
+                address,
+                earlier
+            ));
+        }
+        check_routable(options, *address)?;
+        info!("Using the given IP address {}", address);
+    }
+    with_deadline(
+        options,
+        update_all(options, addresses, update_dns, notifier, names),
+    )
+    .await
+}
+
+async fn with_deadline<T>(
+    options: &RunOptions,
+    run: impl Future<Output = color_eyre::Result<T>>,
+) -> color_eyre::Result<T> {
+    match options.deadline {
+        // Whatever was in flight is dropped, so some records may have been updated and others not
+        Some(deadline) => tokio::time::timeout(deadline, run).await.map_err(|_| {
+            eyre!(
+                "Run timed out, it didn't finish within {} seconds",
+                deadline.as_secs_f64()
+            )
+        })?,
+        None => run.await,
+    }
+}
+
+/// Looks up the public IPv4 and IPv6 addresses at the same time, leaving out a family that can't be
+/// found. Fails only if neither can.
+pub async fn resolve_addresses(
+    options: &RunOptions,
+    ip_source: &dyn IpSource,
+) -> color_eyre::Result<Vec<IpAddr>> {
+    info!("Resolving public IP with {}", ip_source.describe());
+    let started = Instant::now();
+    // Neither family waits on the other, and one failing leaves the other to finish
+    let (v4, v6) = tokio::join!(
+        timed("IPv4", started, ip_source.public_ipv4()),
+        timed("IPv6", started, ip_source.public_ipv6()),
+    );
+    debug!(
+        "Looking up the public IP addresses took {:?}",
+        started.elapsed()
+    );
+    let v4 = v4
+        .and_then(|ip| check_routable(options, ip.into()).map(|()| ip))
+        .map_err(|e| {
+            warn!(
+                "Unable to resolve public IPv4 address, skipping A records: {:#}",
+                e
+            )
+        })
+        .ok();
+    let v6 = v6
+        .and_then(|ip| check_routable(options, ip.into()).map(|()| ip))
+        .map_err(|e| {
+            warn!(
+                "Unable to resolve public IPv6 address, skipping AAAA records: {:#}",
+                e
+            )
+        })
+        .ok();
+    let addresses: Vec<IpAddr> = v4
+        .map(IpAddr::V4)
+        .into_iter()
+        .chain(v6.map(IpAddr::V6))
+        .collect();
+    if addresses.is_empty() {
+        return Err(eyre!("No public IP addresses could be resolved"));
+    }
+
+    for address in &addresses {
+        info!("Your public IP address is {}", address);
+    }
+    Ok(addresses)
+}
+
+/// Each family is timed on its own, as one timing out would otherwise hide how long the other took.
+async fn timed<T>(family: &str, started: Instant, lookup: impl Future<Output = T>) -> T {
+    let result = lookup.await;
+    debug!(
+        "Looking up the public {} address took {:?}",
+        family,
+        started.elapsed()
+    );
+    result
+}
+
+/// Sends `ip` to every provider for every name in `config`, without looking it up, checking it or
+/// remembering it. For callers that already know their address.
+pub async fn run(config: Secrets, ip: IpAddr) -> color_eyre::Result<Vec<UpdateOutcome>> {
+    let update_dns: Vec<Provider> = config
+        .update_dns
+        .into_vec()
+        .into_iter()
+        .map(Provider::from)
+        .collect();
+    let http = &config.http;
+    let notifier = config.notify.map(|notify| Notifier::new(notify, http));
+    update_all(
+        &RunOptions::default(),
+        &[ip],
+        &update_dns,
+        notifier.as_ref(),
+        &config.dns_name.into_vec(),
+    )
+    .await
+}
+
+async fn update_all(
+    options: &RunOptions,
+    addresses: &[IpAddr],
+    update_dns: &[Provider],
+    notifier: Option<&Notifier>,
+    names: &[String],
+) -> color_eyre::Result<Vec<UpdateOutcome>> {
+    for provider in update_dns {
+        info!(
+            "Attempting to update DNS entry with {}",
+            provider.dns.describe()
+        );
+    }
+
+    let cache_path = &options.cache_file;
+    let mut cache = cache_path.as_deref().map(IpCache::load).unwrap_or_default();
+
+    let mut outcomes = Vec::new();
+    let mut changes = Vec::new();
+    let mut summary = Vec::new();
+    let mut report = Vec::new();
+    let mut to_verify: Vec<(String, IpAddr, &PropagationConfig)> = Vec::new();
+    let mut attempted = Vec::new();
+    let mut failed = Vec::new();
+    let mut updated_any = false;
+    for address in addresses {
+        let mut pending = Vec::new();
+        for name in names {
+            if !options.force && cache.contains(name, *address) {
+                info!(
+                    "{} was already updated to {}, skipping. Use --force to update anyway.",
+                    name, address
+                );
+                let outcome = UpdateOutcome::unchanged(*address);
+                summary.push(format!("{}: {} (cached)", name, outcome));
+                report.push(SummaryEntry::cached(name, *address));
+                outcomes.push(outcome);
+            } else if let Some(since) = options
+                .min_change_interval
+                .filter(|_| !options.force)
+                .and_then(|interval| {
+                    cache.changed_within(name, *address, interval, SystemTime::now())
+                })
+            {
+                info!(
+                    "{} got a new address {} seconds ago, not changing it to {} until \
+                     min_change_interval_secs has passed. Use --force to update anyway.",
+                    name,
+                    since.as_secs(),
+                    address
+                );
+                let outcome = UpdateOutcome::not_written(cache.last(name, *address), *address);
+                summary.push(format!("{}: {} (held)", name, outcome));
+                report.push(SummaryEntry::held(name, *address));
+                outcomes.push(outcome);
+            } else {
+                pending.push(name.clone());
+            }
+        }
+        if pending.is_empty() {
+            continue;
+        }
+
+        let providers = update_dns.iter().filter(|p| p.handles(*address));
+        let provider_results: Vec<(&Provider, Vec<UpdateResult>)> = if options.stagger.is_zero() {
+            update_concurrently(options, providers, &pending, *address).await
+        } else {
+            let mut provider_results = Vec::new();
+            for provider in providers {
+                let mut results = Vec::with_capacity(pending.len());
+                for name in &pending {
+                    if updated_any {
+                        tokio::time::sleep(options.stagger).await;
+                    }
+                    updated_any = true;
+                    results.push(
+                        provider
+                            .dns
+                            .update_dns(name.clone(), *address, options.dry_run)
+                            .await,
+                    );
+                }
+                provider_results.push((provider, results));
+            }
+            provider_results
+        };
+
+        let mut failed_names = Vec::new();
+        for (provider, results) in provider_results {
+            for (name, result) in pending.iter().zip(results) {
+                // Only worth saying which provider when there's more than one
+                let label = if update_dns.len() > 1 {
+                    format!("{} via {}", name, provider.dns.describe())
+                } else {
+                    name.clone()
+                };
+                if !attempted.contains(&label) {
+                    attempted.push(label.clone());
+                }
+                report.push(SummaryEntry::from_result(
+                    name,
+                    provider.dns.describe(),
+                    *address,
+                    &result,
+                ));
+                match result {
+                    Ok(outcome) => {
+                        // Only a new address is news, not e.g. a TTL being corrected
+                        let changed = outcome.written && outcome.old_ip != Some(outcome.new_ip);
+                        // A TXT or CNAME record can't be checked by looking the address up
+                        if let (Some(verify), true, RecordType::Address) =
+                            (&provider.verify, changed, provider.dns.record_type())
+                        {
+                            to_verify.push((name.clone(), outcome.new_ip, verify));
+                        }
+                        if let Some(modified_on) = outcome.modified_on {
+                            check_modified_on(
+                                &mut cache,
+                                name,
+                                &label,
+                                outcome.new_ip,
+                                modified_on,
+                                options.dry_run,
+                            );
+                        }
+                        if changed {
+                            cache.record_change(name, outcome.new_ip, SystemTime::now());
+                            changes.push(HistoryEntry::now(
+                                name,
+                                outcome.old_ip,
+                                outcome.new_ip,
+                                provider.dns.describe(),
+                            ));
+                        }
+                        if let (Some(notifier), true) = (notifier, changed) {
+                            let change = IpChange {
+                                dns_name: name,
+                                old_ip: outcome.old_ip,
+                                new_ip: outcome.new_ip,
+                                provider: provider.dns.describe(),
+                            };
+                            if let Err(e) = notifier.notify(&change).await {
+                                warn!("Failed to send notification for {}: {:#}", name, e);
+                            }
+                        }
+                        summary.push(format!("{}: {}", label, outcome));
+                        outcomes.push(outcome);
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to update DNS entry {} to {}: {:?}",
+                            label, address, e
+                        );
+                        summary.push(format!("{}: failed to set {}", label, address));
+                        if !failed_names.contains(name) {
+                            failed_names.push(name.clone());
+                        }
+                        if !failed.contains(&label) {
+                            failed.push(label);
+                        }
+                    }
+                }
+            }
+        }
+        // Left uncached if any provider failed, so they're all tried again next time
+        for name in &pending {
+            if !options.dry_run && !failed_names.contains(name) {
+                cache.record(name, *address);
+            }
+        }
+    }
+
+    join_all(
+        to_verify
+            .into_iter()
+            .map(|(name, ip, verify)| async move { verify.wait_for(&name, ip).await }),
+    )
+    .await;
+
+    if let Some(path) = cache_path {
+        if let Err(e) = cache.save(path) {
+            warn!("Failed to save IP cache: {:#}", e);
+        }
+    }
+    if let Some(history) = &options.history {
+        history.append(changes);
+    }
+
+    match options.summary_format {
+        SummaryFormat::Text => {
+            info!("Summary:");
+            for line in &summary {
+                info!("  {}", line);
+            }
+        }
+        SummaryFormat::Json => match serde_json::to_string(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => warn!("Failed to serialize summary: {}", e),
+        },
+    }
+
+    if !failed.is_empty() {
+        return Err(eyre!(
+            "Failed to update {} of {} DNS entries: {}",
+            failed.len(),
+            attempted.len(),
+            failed.join(", ")
+        ));
+    }
+
+    Ok(outcomes)
+}
+
+/// Every provider at once, with at most `options.concurrency` requests in flight. A provider that
+/// batches gets all of the names in one go, which counts as one; any other gets each name on its own.
+async fn update_concurrently<'p>(
+    options: &RunOptions,
+    providers: impl Iterator<Item = &'p Provider>,
+    names: &[String],
+    address: IpAddr,
+) -> Vec<(&'p Provider, Vec<UpdateResult>)> {
+    let limit = Semaphore::new(options.concurrency.max(1));
+    let limit = &limit;
+    join_all(providers.map(|provider| async move {
+        let results = if provider.dns.batches() {
+            let _permit = limit.acquire().await.expect("Semaphore is never closed");
+            provider
+                .dns
+                .update_many(names, address, options.dry_run)
+                .await
+        } else {
+            join_all(names.iter().map(|name| async move {
+                let _permit = limit.acquire().await.expect("Semaphore is never closed");
+                provider
+                    .dns
+                    .update_dns(name.clone(), address, options.dry_run)
+                    .await
+            }))
+            .await
+        };
+        (provider, results)
+    }))
+    .await
+}
+
+/// Warns if the record was modified since the last run by something other than this tool, then
+/// remembers when it was last modified for next time. Only a warning, the update still goes ahead.
+fn check_modified_on(
+    cache: &mut IpCache,
+    name: &str,
+    label: &str,
+    ip: IpAddr,
+    modified_on: ModifiedOn,
+    dry_run: bool,
+) {
+    if let (Some(last), Some(found)) = (cache.modified_on(name, ip), modified_on.found) {
+        if found != last {
+            warn!(
+                "{} record externally modified since last run (modified_on was {}, now {})",
+                label,
+                last.to_rfc3339(),
+                found.to_rfc3339()
+            );
+        }
+    }
+    if !dry_run {
+        cache.record_modified_on(name, ip, modified_on.now);
+    }
+}
+
+fn check_routable(options: &RunOptions, ip: IpAddr) -> color_eyre::Result<()> {
+    if options.allow_private {
+        return Ok(());
+    }
+    ip_source::check_globally_routable(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::config::all_families;
+
+    struct FixedIpSource;
+
+    #[async_trait]
+    impl IpSource for FixedIpSource {
+        fn describe(&self) -> String {
+            "Fixed".to_string()
+        }
+
+        async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+            Ok(Ipv4Addr::new(203, 0, 113, 5))
+        }
+
+        async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+            Err(eyre!("No IPv6 here"))
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingUpdateDns {
+        calls: Arc<Mutex<Vec<(String, IpAddr)>>>,
+    }
+
+    #[async_trait]
+    impl UpdateDns for RecordingUpdateDns {
+        fn describe(&self) -> String {
+            "Recording".to_string()
+        }
+
+        async fn update_dns(&self, name: String, new_ip: IpAddr, _dry_run: bool) -> UpdateResult {
+            self.calls.lock().unwrap().push((name, new_ip));
+            Ok(UpdateOutcome::written(None, new_ip))
+        }
+    }
+
+    struct FailingUpdateDns;
+
+    #[async_trait]
+    impl UpdateDns for FailingUpdateDns {
+        fn describe(&self) -> String {
+            "Failing".to_string()
+        }
+
+        async fn update_dns(&self, _name: String, _new_ip: IpAddr, _dry_run: bool) -> UpdateResult {
+            Err(eyre!("Provider is down").into())
+        }
+    }
+
+    fn provider(dns: impl UpdateDns + 'static, families: Vec<Family>) -> Provider {
+        Provider {
+            dns: Box::new(dns),
+            families,
+            verify: None,
+        }
+    }
+
+    fn test_options(test_name: &str) -> RunOptions {
+        RunOptions {
+            force: true,
+            cache_file: Some(std::env::temp_dir().join(format!(
+                "box-dyn-dns-test-{}-{}",
+                std::process::id(),
+                test_name
+            ))),
+            ..RunOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn single_name_is_updated_once_per_resolved_address() {
+        let options = test_options("single-name");
+        let update_dns = RecordingUpdateDns::default();
+        let calls = update_dns.calls.clone();
+
+        run_once(
+            &options,
+            &FixedIpSource,
+            &[provider(update_dns, all_families())],
+            None,
+            &["home.example.com".to_string()],
+        )
+        .await
+        .unwrap();
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [(
+                "home.example.com".to_string(),
+                IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5))
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_provider_does_not_stop_the_others() {
+        let options = test_options("failing-provider");
+        let update_dns = RecordingUpdateDns::default();
+        let calls = update_dns.calls.clone();
+
+        let result = run_once(
+            &options,
+            &FixedIpSource,
+            &[
+                provider(FailingUpdateDns, all_families()),
+                provider(update_dns, all_families()),
+            ],
+            None,
+            &["home.example.com".to_string()],
+        )
+        .await;
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("home.example.com via Failing"), "{}", error);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn provider_is_only_given_its_families() {
+        let options = test_options("families");
+        let v4_dns = RecordingUpdateDns::default();
+        let v4_calls = v4_dns.calls.clone();
+        let v6_dns = RecordingUpdateDns::default();
+        let v6_calls = v6_dns.calls.clone();
+
+        run_once(
+            &options,
+            &FixedIpSource,
+            &[
+                provider(v4_dns, vec![Family::V4]),
+                provider(v6_dns, vec![Family::V6]),
+            ],
+            None,
+            &["home.example.com".to_string()],
+        )
+        .await
+        .unwrap();
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        assert_eq!(v4_calls.lock().unwrap().len(), 1);
+        assert!(v6_calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn given_addresses_are_checked_then_used() {
+        let options = test_options("given-addresses");
+        let update_dns = RecordingUpdateDns::default();
+        let calls = update_dns.calls.clone();
+        let providers = [provider(update_dns, all_families())];
+        let names = ["home.example.com".to_string()];
+        let v4 = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+        let v6 = IpAddr::V6("2001:db8::7".parse().unwrap());
+
+        let private = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 7));
+        assert!(
+            run_with_addresses(&options, &[private], &providers, None, &names)
+                .await
+                .is_err()
+        );
+        assert!(
+            run_with_addresses(&options, &[v4, v4], &providers, None, &names)
+                .await
+                .is_err()
+        );
+        run_with_addresses(&options, &[v4, v6], &providers, None, &names)
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("home.example.com".to_string(), v4),
+                ("home.example.com".to_string(), v6)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn both_families_can_change_in_one_run() {
+        let options = RunOptions {
+            force: false,
+            min_change_interval: Some(Duration::from_secs(3600)),
+            ..test_options("both-families-change")
+        };
+        let update_dns = RecordingUpdateDns::default();
+        let calls = update_dns.calls.clone();
+        let providers = [provider(update_dns, all_families())];
+        let names = ["home.example.com".to_string()];
+        let v4 = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+        let v6 = IpAddr::V6("2001:db8::7".parse().unwrap());
+
+        let outcomes = run_with_addresses(&options, &[v4, v6], &providers, None, &names).await;
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        // The new A record mustn't hold back the AAAA one
+        assert!(outcomes.unwrap().iter().all(|outcome| outcome.written));
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn run_sends_the_given_address_to_every_name() {
+        let config = crate::config::parse_config(
+            r#"
+dns_name: [a.example.com, b.example.com]
+update_dns:
+  stdout: {}
+"#,
+            crate::config::ConfigFormat::Yaml,
+        )
+        .unwrap();
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+
+        let outcomes = run(config, ip).await.unwrap();
+
+        assert_eq!(outcomes, [UpdateOutcome::written(None, ip); 2]);
+    }
+
+    #[tokio::test]
+    async fn stagger_spaces_out_updates() {
+        let options = RunOptions {
+            stagger: Duration::from_millis(50),
+            ..test_options("stagger")
+        };
+        let update_dns = RecordingUpdateDns::default();
+        let calls = update_dns.calls.clone();
+
+        let start = std::time::Instant::now();
+        run_once(
+            &options,
+            &FixedIpSource,
+            &[provider(update_dns, all_families())],
+            None,
+            &["a.example.com".to_string(), "b.example.com".to_string()],
+        )
+        .await
+        .unwrap();
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        assert_eq!(calls.lock().unwrap().len(), 2);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn run_past_the_deadline_is_abandoned() {
+        let options = RunOptions {
+            stagger: Duration::from_secs(10),
+            deadline: Some(Duration::from_millis(50)),
+            ..test_options("deadline")
+        };
+        let update_dns = RecordingUpdateDns::default();
+        let calls = update_dns.calls.clone();
+
+        let error = run_once(
+            &options,
+            &FixedIpSource,
+            &[provider(update_dns, all_families())],
+            None,
+            &["a.example.com".to_string(), "b.example.com".to_string()],
+        )
+        .await
+        .unwrap_err();
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        assert_eq!(
+            error.to_string(),
+            "Run timed out, it didn't finish within 0.05 seconds"
+        );
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[derive(Default)]
+    struct SlowUpdateDns {
+        in_flight: std::sync::atomic::AtomicUsize,
+        most_in_flight: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl UpdateDns for SlowUpdateDns {
+        fn describe(&self) -> String {
+            "Slow".to_string()
+        }
+
+        async fn update_dns(&self, _name: String, new_ip: IpAddr, _dry_run: bool) -> UpdateResult {
+            use std::sync::atomic::Ordering;
+
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(UpdateOutcome::written(None, new_ip))
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrency_bounds_updates_in_flight() {
+        let options = RunOptions {
+            concurrency: 2,
+            ..test_options("concurrency")
+        };
+        let update_dns = Arc::new(SlowUpdateDns::default());
+        let names: Vec<String> = (0..5).map(|i| format!("{}.example.com", i)).collect();
+
+        let outcomes = update_all(
+            &options,
+            &[IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5))],
+            &[Provider {
+                dns: Box::new(SharedUpdateDns(update_dns.clone())),
+                families: all_families(),
+                verify: None,
+            }],
+            None,
+            &names,
+        )
+        .await
+        .unwrap();
+        let _ = std::fs::remove_file(options.cache_file.unwrap());
+
+        assert_eq!(outcomes.len(), 5);
+        assert_eq!(
+            update_dns
+                .most_in_flight
+                .load(std::sync::atomic::Ordering::SeqCst),
+            2
+        );
+    }
+
+    /// So a test can keep hold of a provider after handing it over.
+    struct SharedUpdateDns<D>(Arc<D>);
+
+    #[async_trait]
+    impl<D: UpdateDns> UpdateDns for SharedUpdateDns<D> {
+        fn describe(&self) -> String {
+            self.0.describe()
+        }
+
+        async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+            self.0.update_dns(name, new_ip, dry_run).await
+        }
+    }
+
+    /// Takes two seconds over each family, and never finds IPv6.
+    struct SlowIpSource;
+
+    #[async_trait]
+    impl IpSource for SlowIpSource {
+        fn describe(&self) -> String {
+            "Slow".to_string()
+        }
+
+        async fn public_ipv4(&self) -> color_eyre::Result<Ipv4Addr> {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            Ok(Ipv4Addr::new(203, 0, 113, 5))
+        }
+
+        async fn public_ipv6(&self) -> color_eyre::Result<Ipv6Addr> {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            Err(eyre!("No IPv6 here"))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn families_are_resolved_at_the_same_time() {
+        let options = test_options("concurrent-families");
+        let started = tokio::time::Instant::now();
+
+        let addresses = resolve_addresses(&options, &SlowIpSource).await.unwrap();
+
+        assert_eq!(addresses, vec![IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5))]);
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+    }
+}
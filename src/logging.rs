@@ -0,0 +1,244 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use color_eyre::eyre::WrapErr;
+use log::{LevelFilter, Log, Metadata, Record};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "Unknown log format {:?}, expected one of pretty, json",
+                s
+            )),
+        }
+    }
+}
+
+/// `verbosity` counts like stderrlog's: 0 is errors only, up to 4 for everything. With a `file`,
+/// everything logged to stderr is written there too.
+pub(crate) fn init(
+    format: LogFormat,
+    verbosity: usize,
+    file: Option<LogFile>,
+) -> color_eyre::Result<()> {
+    let level = match verbosity {
+        0 => LevelFilter::Error,
+        1 => LevelFilter::Warn,
+        2 => LevelFilter::Info,
+        3 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let stderr: Box<dyn Log> = match format {
+        LogFormat::Pretty => {
+            let mut stderrlog = stderrlog::new();
+            stderrlog.verbosity(verbosity);
+            Box::new(stderrlog)
+        }
+        LogFormat::Json => Box::new(JsonLogger { level }),
+    };
+    let logger = match file {
+        Some(file) => Box::new(TeeLogger {
+            stderr,
+            format,
+            level,
+            file: Mutex::new(file),
+        }),
+        None => stderr,
+    };
+    log::set_boxed_logger(logger).wrap_err("Failed to initialize logging")?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+fn json_line(record: &Record) -> String {
+    serde_json::json!({
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    })
+    .to_string()
+}
+
+/// One JSON object per line on stderr, for log pipelines.
+struct JsonLogger {
+    level: LevelFilter,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = writeln!(std::io::stderr().lock(), "{}", json_line(record));
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Sends records to the stderr logger and appends them to a file. The file gets timestamps, as
+/// the pretty stderr output has none.
+struct TeeLogger {
+    stderr: Box<dyn Log>,
+    format: LogFormat,
+    level: LevelFilter,
+    file: Mutex<LogFile>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.stderr.log(record);
+        let line = match self.format {
+            LogFormat::Pretty => format!(
+                "{} {} - {}",
+                chrono::Utc::now().to_rfc3339(),
+                record.level(),
+                record.args()
+            ),
+            LogFormat::Json => json_line(record),
+        };
+        if let Ok(mut file) = self.file.lock() {
+            if let Err(e) = file.write_line(&line) {
+                self.stderr.log(
+                    &Record::builder()
+                        .level(log::Level::Warn)
+                        .target(module_path!())
+                        .args(format_args!("Failed to write log file: {}", e))
+                        .build(),
+                );
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// A log file that's moved aside once it reaches `max_size` bytes, keeping `keep` old files as
+/// `<path>.1` (the newest) to `<path>.<keep>`.
+pub(crate) struct LogFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    pub(crate) fn open(path: PathBuf, max_size: u64, keep: usize) -> color_eyre::Result<LogFile> {
+        let file = LogFile::open_append(&path)
+            .wrap_err_with(|| format!("Failed to open log file {}", path.display()))?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Ok(LogFile {
+            path,
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn open_append(path: &Path) -> std::io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            for index in (1..self.keep).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+            self.file = LogFile::open_append(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_file_is_rotated() {
+        let dir = std::env::temp_dir().join(format!("box-dyn-dns-test-{}-log", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("box-dyn-dns.log");
+
+        let mut file = LogFile::open(path.clone(), 10, 2).unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+        let contents = (
+            read(path.clone()),
+            read(file.rotated(1)),
+            read(file.rotated(2)),
+            file.rotated(3).exists(),
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(
+            contents,
+            (
+                "fourth\n".to_string(),
+                "third\n".to_string(),
+                "second\n".to_string(),
+                false
+            )
+        );
+    }
+}
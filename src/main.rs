@@ -1,103 +1,406 @@
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use color_eyre::eyre::{ContextCompat, WrapErr};
-use log::info;
-use serde::Deserialize;
+use box_dyn_dns::config::{built_in_providers, load_config, ConfigFormat, IpSourceConfig};
+use box_dyn_dns::ip_source::api::IpSource;
+use box_dyn_dns::notify::Notifier;
+use box_dyn_dns::{
+    http, resolve_addresses, run_check, run_once, run_with_addresses, HistoryFile, Provider,
+    RunOptions, SummaryFormat,
+};
+use color_eyre::eyre::{eyre, WrapErr};
+use log::{debug, error, info};
+use rand::Rng;
 use structopt::StructOpt;
-use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
-use trust_dns_resolver::Resolver;
+use url::Url;
 
-use crate::update_dns::api::{UpdateDns, UpdateDnsCreator};
-use crate::update_dns::cloudflare::{Cloudflare, CloudflareConfig};
+use crate::logging::{LogFile, LogFormat};
+use crate::metrics::Metrics;
+use crate::systemd::Systemd;
 
-mod update_dns;
+mod logging;
+mod metrics;
+mod systemd;
 
 const RUST_BACKTRACE: &str = "RUST_BACKTRACE";
 
 #[derive(StructOpt, Debug)]
-pub(crate) struct BoxDynDns {
-    /// Verbosity of output, 1 occurrence for debug, 2 occurrences for trace
+struct BoxDynDns {
+    /// Verbosity of output, 1 occurrence for debug, 2 or more for trace
     #[structopt(short, long, parse(from_occurrences))]
     pub verbose: usize,
+    /// Only log errors, e.g. for cron. Overrides --verbose
+    #[structopt(short, long)]
+    pub quiet: bool,
+    /// Path to the secrets file
+    #[structopt(short, long, default_value = "./secrets.yml", parse(from_os_str))]
+    pub config: PathBuf,
+    /// Format of the secrets file: `yaml`, `toml` or `json`. Defaults to guessing from the
+    /// extension, falling back to YAML
+    #[structopt(long)]
+    pub config_format: Option<ConfigFormat>,
+    /// Keep running, re-checking the public IP every this many seconds
+    #[structopt(long)]
+    pub interval: Option<u64>,
+    /// With --interval, move each wait up to this many seconds earlier or later at random, and wait
+    /// up to this long before the first run, so many hosts don't all update at once
+    #[structopt(long, requires = "interval")]
+    pub jitter: Option<u64>,
+    /// Update records even if the cache says they already have the current IP
+    #[structopt(long)]
+    pub force: bool,
+    /// Where to remember the last applied IPs, defaults to `~/.cache/box-dyn-dns/last_ip`
+    #[structopt(long, parse(from_os_str))]
+    pub cache_file: Option<PathBuf>,
+    /// How to find the public IP: `opendns`, `https`, `doh`, `google`, `ipify` or `icanhazip`. Overrides
+    /// the secrets file
+    #[structopt(long)]
+    pub ip_source: Option<IpSourceConfig>,
+    /// Only log the changes that would be made, without making them
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// Send provider requests, and the `https` IP source's, through this HTTP(S) or SOCKS5 proxy.
+    /// Overrides the secrets file
+    #[structopt(long, parse(try_from_str = http::parse_proxy))]
+    pub proxy: Option<Url>,
+    /// Log output format: `pretty` or `json`, one object per line
+    #[structopt(long, default_value = "pretty")]
+    pub log_format: LogFormat,
+    /// Without --interval, exit with 0 if a record was updated, 2 if nothing needed changing and 1
+    /// on failure
+    #[structopt(long, conflicts_with = "interval")]
+    pub once_then_exit_code: bool,
+    /// Accept private, loopback, link-local and CGNAT addresses as the public IP, for testing
+    #[structopt(long)]
+    pub allow_private: bool,
+    /// Check that the secrets file loads and the provider credentials work, then exit without
+    /// updating anything
+    #[structopt(long, conflicts_with = "interval")]
+    pub check: bool,
+    /// Print the secrets file as it was read, with tokens, passwords and other credentials hidden,
+    /// then exit without updating anything
+    #[structopt(long, conflicts_with_all = &["interval", "check"])]
+    pub config_test: bool,
+    /// Print the public IP addresses to stdout, one per line, then exit without reading the secrets
+    /// file or updating anything. Uses --ip-source, or OpenDNS by default
+    #[structopt(long, conflicts_with_all = &["interval", "check"])]
+    pub print_ip: bool,
+    /// Write Prometheus metrics to this file after each run, for node_exporter's textfile collector
+    #[structopt(long, parse(from_os_str))]
+    pub metrics_file: Option<PathBuf>,
+    /// Also write the log to this file
+    #[structopt(long, parse(from_os_str))]
+    pub log_file: Option<PathBuf>,
+    /// Start a new log file once it reaches this many bytes
+    #[structopt(long, default_value = "10485760")]
+    pub log_max_size: u64,
+    /// How many full log files to keep, as `<log-file>.1` and so on
+    #[structopt(long, default_value = "5")]
+    pub log_keep: usize,
+    /// Wait this many milliseconds between one record update and the next. Names are then sent one
+    /// at a time, rather than batched
+    #[structopt(long, default_value = "0")]
+    pub stagger: u64,
+    /// Fail a run that takes longer than this many seconds in total, looking up the IP and updating
+    /// every record. With --interval it applies to each run
+    #[structopt(long)]
+    pub deadline: Option<u64>,
+    /// Keep a JSON log of every record pointed at a new address in this file
+    #[structopt(long, parse(from_os_str))]
+    pub history_file: Option<PathBuf>,
+    /// How many of the latest changes --history-file keeps
+    #[structopt(long, default_value = "100")]
+    pub history_keep: usize,
+    /// Update at most this many records at once. A provider's batch request counts as one
+    #[structopt(long, default_value = "4")]
+    pub concurrency: usize,
+    /// How to report each record at the end of a run: `text` in the log, or `json`, an array on
+    /// stdout. The exit code is non-zero if any failed
+    #[structopt(long, default_value = "text")]
+    pub summary_format: SummaryFormat,
+    /// Use this address instead of finding the public IP, e.g. to try out a provider. Give it
+    /// twice for an IPv4 and an IPv6 address. Still checked unless --allow-private
+    #[structopt(
+        long = "ip",
+        number_of_values = 1,
+        conflicts_with_all = &["interval", "print-ip", "ip-source"]
+    )]
+    pub ip: Vec<IpAddr>,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
 }
 
-fn main() -> color_eyre::Result<()> {
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// List the built-in DNS providers and the settings each one requires
+    Providers,
+}
+
+fn print_providers() {
+    for info in built_in_providers() {
+        println!("{:<14} {}", info.tag, info.description);
+        let required = (info.required_fields)();
+        if !required.is_empty() {
+            println!("{:<14} requires: {}", "", required.join(", "));
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
     if std::env::var_os(RUST_BACKTRACE).is_none() {
         std::env::set_var(RUST_BACKTRACE, "1");
     }
 
-    let args: BoxDynDns = BoxDynDns::from_args();
+    let mut args: BoxDynDns = BoxDynDns::from_args();
+    if let Some(Command::Providers) = args.command {
+        print_providers();
+        return Ok(());
+    }
 
     color_eyre::install()?;
-    stderrlog::new()
-        .verbosity(args.verbose + 2)
-        .init()
-        .wrap_err("Failed to initialize logging")?;
-
-    let config = load_config()?;
-
-    let resolver = Resolver::new(
-        ResolverConfig::from_parts(
-            None,
-            vec![],
-            NameServerConfigGroup::from_ips_clear(
-                &[IpAddr::V4(Ipv4Addr::new(208, 67, 222, 222))],
-                53,
-                true,
-            ),
-        ),
-        ResolverOpts::default(),
-    )
-    .wrap_err("Failed to initialize resolver")?;
-    let response = resolver
-        .lookup_ip("myip.opendns.com.")
-        .wrap_err("Failed to resolve IP address")?;
-    let address = response
-        .iter()
-        .filter_map(|x| match x {
-            IpAddr::V4(v4) => Some(v4),
-            _ => None,
-        })
-        .next()
-        .wrap_err("No IPv4 addresses returned")?;
+    let log_file = args
+        .log_file
+        .take()
+        .map(|path| LogFile::open(path, args.log_max_size, args.log_keep))
+        .transpose()?;
+    let verbosity = if args.quiet { 0 } else { args.verbose + 2 };
+    logging::init(args.log_format, verbosity, log_file)?;
+
+    let mut options = RunOptions {
+        force: args.force,
+        dry_run: args.dry_run,
+        cache_file: args
+            .cache_file
+            .clone()
+            .or_else(box_dyn_dns::default_cache_path),
+        allow_private: args.allow_private,
+        stagger: Duration::from_millis(args.stagger),
+        deadline: args.deadline.map(Duration::from_secs),
+        history: args.history_file.take().map(|path| HistoryFile {
+            path,
+            keep: args.history_keep,
+        }),
+        concurrency: args.concurrency,
+        summary_format: args.summary_format,
+        min_change_interval: None,
+    };
+    if args.print_ip {
+        let mut ip_source = args.ip_source.take().unwrap_or_default();
+        if let Some(proxy) = &args.proxy {
+            for http in ip_source.http_options() {
+                http.proxy = Some(proxy.clone());
+            }
+        }
+        let ip_source: Box<dyn IpSource> = ip_source.into();
+        for address in resolve_addresses(&options, &*ip_source).await? {
+            println!("{}", address);
+        }
+        return Ok(());
+    }
+
+    let mut config = load_config(&args.config, args.config_format)?;
+    if args.config_test {
+        println!("{:#?}", config);
+        return Ok(());
+    }
+    options.min_change_interval = config.min_change_interval_secs.map(Duration::from_secs);
+    if let Some(proxy) = &args.proxy {
+        for update_dns in config.update_dns.iter_mut() {
+            if let Some(http) = update_dns.http_options() {
+                http.proxy = Some(proxy.clone());
+            }
+        }
+    }
+
+    let mut ip_source = args.ip_source.take().unwrap_or(config.ip_source);
+    for http in ip_source.http_options() {
+        // A source picked on the command line hasn't had the file's settings applied yet
+        http.inherit(&config.http);
+        if let Some(proxy) = &args.proxy {
+            http.proxy = Some(proxy.clone());
+        }
+    }
+    let ip_source: Box<dyn IpSource> = ip_source.into();
+    let update_dns: Vec<Provider> = config
+        .update_dns
+        .into_vec()
+        .into_iter()
+        .map(Provider::from)
+        .collect();
+    if args.check {
+        return run_check(&update_dns).await;
+    }
+    let names = config.dns_name.into_vec();
+    let http = &config.http;
+    let notifier = config.notify.map(|notify| Notifier::new(notify, http));
+    let mut metrics = args.metrics_file.take().map(Metrics::new);
+
+    match args.interval {
+        Some(interval) => {
+            let jitter = args.jitter.unwrap_or(0);
+            if jitter > interval {
+                return Err(eyre!("--jitter can't be more than --interval"));
+            }
+            run_daemon(
+                &options,
+                &*ip_source,
+                &update_dns,
+                notifier.as_ref(),
+                &names,
+                metrics.as_mut(),
+                Schedule {
+                    interval: Duration::from_secs(interval),
+                    jitter: Duration::from_secs(jitter),
+                },
+            )
+            .await
+        }
+        None => {
+            let result = if args.ip.is_empty() {
+                run_once(
+                    &options,
+                    &*ip_source,
+                    &update_dns,
+                    notifier.as_ref(),
+                    &names,
+                )
+                .await
+            } else {
+                run_with_addresses(&options, &args.ip, &update_dns, notifier.as_ref(), &names).await
+            };
+            if let Some(metrics) = &mut metrics {
+                metrics.record(&result);
+            }
+            let outcomes = result?;
+            if args.once_then_exit_code && !outcomes.iter().any(|outcome| outcome.written) {
+                std::process::exit(2);
+            }
+            Ok(())
+        }
+    }
+}
 
-    info!("Your public IP address is {}", address);
+/// How long the daemon waits between runs.
+struct Schedule {
+    interval: Duration,
+    /// No more than `interval`, so waits can't be pushed below zero and the average stays put.
+    jitter: Duration,
+}
 
-    let update_dns: Box<dyn UpdateDns> = config.update_dns.into();
+impl Schedule {
+    fn first_delay(&self) -> Duration {
+        self.jitter.mul_f64(rand::thread_rng().gen::<f64>())
+    }
 
-    info!(
-        "Attempting to update DNS entry with {}",
-        update_dns.describe()
-    );
+    fn next_delay(&self) -> Duration {
+        // Durations can't go negative, so this adds 0 to 2 × jitter and takes one jitter back off
+        let offset = self
+            .jitter
+            .mul_f64(rand::thread_rng().gen_range(-1.0..=1.0) + 1.0);
+        (self.interval + offset).saturating_sub(self.jitter)
+    }
+}
 
-    update_dns
-        .update_dns(config.dns_name, address)
-        .wrap_err("Failed to update DNS entry")?;
+/// Runs updates on `schedule` until SIGINT/SIGTERM. Failed iterations are logged and retried on
+/// the next tick rather than ending the loop.
+async fn run_daemon(
+    options: &RunOptions,
+    ip_source: &dyn IpSource,
+    update_dns: &[Provider],
+    notifier: Option<&Notifier>,
+    names: &[String],
+    mut metrics: Option<&mut Metrics>,
+    schedule: Schedule,
+) -> color_eyre::Result<()> {
+    let mut shutdown = Shutdown::install()?;
+    let systemd = Systemd::from_env();
 
-    Ok(())
+    info!("Running every {} seconds", schedule.interval.as_secs());
+    systemd.ready();
+    let mut delay = schedule.first_delay();
+    loop {
+        if !delay.is_zero() {
+            debug!("Waiting {} seconds", delay.as_secs());
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.recv() => {
+                    info!("Shutting down");
+                    return Ok(());
+                }
+            }
+        }
+        let result = run_once(options, ip_source, update_dns, notifier, names).await;
+        if let Some(metrics) = &mut metrics {
+            metrics.record(&result);
+        }
+        match result {
+            Ok(_) => systemd.watchdog(),
+            Err(e) => error!("Update failed: {:?}", e),
+        }
+        delay = schedule.next_delay();
+    }
 }
 
-fn load_config() -> color_eyre::Result<Secrets> {
-    serde_yaml::from_reader(std::fs::File::open("./secrets.yml")?)
-        .wrap_err("Failed to read secrets")
+/// Signal handlers are installed once up front, so a signal arriving mid-update isn't lost.
+#[cfg(unix)]
+struct Shutdown {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
 }
 
-#[derive(Deserialize, Debug)]
-struct Secrets {
-    dns_name: String,
-    update_dns: UpdateDnsConfig,
+#[cfg(unix)]
+impl Shutdown {
+    fn install() -> color_eyre::Result<Shutdown> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        Ok(Shutdown {
+            interrupt: signal(SignalKind::interrupt())
+                .wrap_err("Failed to install signal handler")?,
+            terminate: signal(SignalKind::terminate())
+                .wrap_err("Failed to install signal handler")?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.interrupt.recv() => {}
+            _ = self.terminate.recv() => {}
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-enum UpdateDnsConfig {
-    #[serde(rename = "cloudflare")]
-    Cloudflare(CloudflareConfig),
+#[cfg(not(unix))]
+struct Shutdown;
+
+#[cfg(not(unix))]
+impl Shutdown {
+    fn install() -> color_eyre::Result<Shutdown> {
+        Ok(Shutdown)
+    }
+
+    async fn recv(&mut self) {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
 
-impl From<UpdateDnsConfig> for Box<dyn UpdateDns> {
-    fn from(config: UpdateDnsConfig) -> Box<dyn UpdateDns> {
-        match config {
-            UpdateDnsConfig::Cloudflare(cf) => Box::from(Cloudflare::from_config(cf)),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let schedule = Schedule {
+            interval: Duration::from_secs(60),
+            jitter: Duration::from_secs(10),
+        };
+
+        for _ in 0..100 {
+            assert!(schedule.first_delay() <= Duration::from_secs(10));
+            let delay = schedule.next_delay();
+            assert!(delay >= Duration::from_secs(50), "{:?}", delay);
+            assert!(delay <= Duration::from_secs(70), "{:?}", delay);
         }
     }
 }
@@ -0,0 +1,139 @@
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use box_dyn_dns::UpdateOutcome;
+use chrono::Utc;
+use color_eyre::eyre::WrapErr;
+use log::warn;
+
+/// Prometheus textfile for node_exporter's textfile collector, rewritten after every run.
+pub(crate) struct Metrics {
+    path: PathBuf,
+    ip_changed_total: u64,
+    /// Kept from the last successful run, so a failed one doesn't blank them out.
+    current_ips: Vec<IpAddr>,
+}
+
+impl Metrics {
+    pub(crate) fn new(path: PathBuf) -> Metrics {
+        Metrics {
+            path,
+            ip_changed_total: 0,
+            current_ips: Vec::new(),
+        }
+    }
+
+    /// Failing to write the file is only logged, it shouldn't stop updates.
+    pub(crate) fn record(&mut self, result: &color_eyre::Result<Vec<UpdateOutcome>>) {
+        if let Ok(outcomes) = result {
+            self.ip_changed_total += outcomes
+                .iter()
+                .filter(|outcome| outcome.written && outcome.old_ip != Some(outcome.new_ip))
+                .count() as u64;
+            self.current_ips.clear();
+            for outcome in outcomes {
+                if !self.current_ips.contains(&outcome.new_ip) {
+                    self.current_ips.push(outcome.new_ip);
+                }
+            }
+        }
+        let contents = self.render(Utc::now().timestamp(), result.is_ok());
+        if let Err(e) = self.write(&contents) {
+            warn!("Failed to write metrics: {:#}", e);
+        }
+    }
+
+    fn render(&self, timestamp: i64, success: bool) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+        metric(
+            "boxdyndns_last_run_timestamp",
+            "gauge",
+            "Unix time the last update run finished.",
+            &[(String::new(), timestamp.to_string())],
+        );
+        metric(
+            "boxdyndns_update_success",
+            "gauge",
+            "Whether the last update run succeeded.",
+            &[(String::new(), (success as u8).to_string())],
+        );
+        metric(
+            "boxdyndns_ip_changed_total",
+            "counter",
+            "Records pointed at a new address since starting.",
+            &[(String::new(), self.ip_changed_total.to_string())],
+        );
+        let current_ips: Vec<(String, String)> = self
+            .current_ips
+            .iter()
+            .map(|ip| (format!("{{ip=\"{}\"}}", ip), "1".to_string()))
+            .collect();
+        metric(
+            "boxdyndns_current_ip",
+            "gauge",
+            "The public addresses last applied.",
+            &current_ips,
+        );
+        out
+    }
+
+    /// Written to a temporary file and renamed, so the collector never reads half a file.
+    fn write(&self, contents: &str) -> color_eyre::Result<()> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        std::fs::write(&temp, contents)
+            .wrap_err_with(|| format!("Failed to write {}", self.path.display()))?;
+        std::fs::rename(&temp, &self.path)
+            .wrap_err_with(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn changes_are_counted_across_runs() {
+        let path =
+            std::env::temp_dir().join(format!("box-dyn-dns-test-{}-metrics", std::process::id()));
+        let mut metrics = Metrics::new(path.clone());
+        let old_ip = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+        let new_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+        metrics.current_ips = vec![old_ip];
+        metrics.ip_changed_total = 1;
+
+        metrics.record(&Ok(vec![
+            UpdateOutcome::written(Some(old_ip), new_ip),
+            UpdateOutcome::unchanged(new_ip),
+        ]));
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            metrics.render(1700000000, true),
+            "\
+# HELP boxdyndns_last_run_timestamp Unix time the last update run finished.
+# TYPE boxdyndns_last_run_timestamp gauge
+boxdyndns_last_run_timestamp 1700000000
+# HELP boxdyndns_update_success Whether the last update run succeeded.
+# TYPE boxdyndns_update_success gauge
+boxdyndns_update_success 1
+# HELP boxdyndns_ip_changed_total Records pointed at a new address since starting.
+# TYPE boxdyndns_ip_changed_total counter
+boxdyndns_ip_changed_total 2
+# HELP boxdyndns_current_ip The public addresses last applied.
+# TYPE boxdyndns_current_ip gauge
+boxdyndns_current_ip{ip=\"203.0.113.5\"} 1
+"
+        );
+    }
+}
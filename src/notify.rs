@@ -0,0 +1,86 @@
+use std::net::IpAddr;
+
+use color_eyre::eyre::{eyre, WrapErr};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+
+#[derive(Deserialize, Debug)]
+pub enum NotifyConfig {
+    /// POSTs the change as JSON to `url`.
+    #[serde(rename = "webhook")]
+    Webhook { url: Secret<String> },
+    /// Posts a readable message to a Discord channel webhook.
+    #[serde(rename = "discord")]
+    Discord { webhook_url: Secret<String> },
+    /// Posts a readable message to a Slack incoming webhook.
+    #[serde(rename = "slack")]
+    Slack { webhook_url: Secret<String> },
+}
+
+/// A record that was changed to a new address.
+#[derive(Serialize, Debug)]
+pub struct IpChange<'a> {
+    pub dns_name: &'a str,
+    pub old_ip: Option<IpAddr>,
+    pub new_ip: IpAddr,
+    pub provider: String,
+}
+
+impl IpChange<'_> {
+    fn message(&self) -> String {
+        match self.old_ip {
+            Some(old_ip) => format!(
+                "{} changed from {} to {} ({})",
+                self.dns_name, old_ip, self.new_ip, self.provider
+            ),
+            None => format!(
+                "{} set to {} ({})",
+                self.dns_name, self.new_ip, self.provider
+            ),
+        }
+    }
+}
+
+pub struct Notifier {
+    config: NotifyConfig,
+    client: Client,
+}
+
+impl Notifier {
+    pub fn new(config: NotifyConfig, http: &HttpOptions) -> Notifier {
+        Notifier {
+            config,
+            client: http.client(),
+        }
+    }
+
+    pub async fn notify(&self, change: &IpChange<'_>) -> color_eyre::Result<()> {
+        let request = match &self.config {
+            NotifyConfig::Webhook { url } => self.client.post(url.as_str()).json(change),
+            NotifyConfig::Discord { webhook_url } => self
+                .client
+                .post(webhook_url.as_str())
+                .json(&serde_json::json!({ "content": change.message() })),
+            NotifyConfig::Slack { webhook_url } => self
+                .client
+                .post(webhook_url.as_str())
+                .json(&serde_json::json!({ "text": change.message() })),
+        };
+        // Webhook URLs have their token in them
+        let response = request
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(eyre!(
+                "{} Error from notification webhook",
+                response.status()
+            ));
+        }
+        Ok(())
+    }
+}
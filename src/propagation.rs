@@ -0,0 +1,142 @@
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::ip_source::resolver;
+
+/// The first wait between lookups, doubled after each one up to [MAX_DELAY].
+const FIRST_DELAY: Duration = Duration::from_secs(2);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How to check that a written record is visible, for a provider's `verify_after_update`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PropagationConfig {
+    /// Resolvers to look the record up with, tried in order. Cloudflare's and Google's public ones
+    /// by default.
+    #[serde(default = "default_resolvers")]
+    pub resolvers: Vec<IpAddr>,
+    /// How long to keep looking before warning that the change hasn't shown up.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for PropagationConfig {
+    fn default() -> Self {
+        PropagationConfig {
+            resolvers: default_resolvers(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+fn default_resolvers() -> Vec<IpAddr> {
+    vec![
+        IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+        IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+    ]
+}
+
+fn default_timeout_secs() -> u64 {
+    120
+}
+
+impl PropagationConfig {
+    /// Looks `name` up until it resolves to `ip`, backing off between tries. Not seeing it only
+    /// warrants a warning, as the provider did accept the change and caches may just be slow.
+    pub(crate) async fn wait_for(&self, name: &str, ip: IpAddr) {
+        let timeout = Duration::from_secs(self.timeout_secs);
+        let seen = wait_until(timeout, FIRST_DELAY, || self.resolves_to(name, ip)).await;
+        if seen {
+            info!("{} now resolves to {}", name, ip);
+        } else {
+            warn!(
+                "{} was updated to {}, but doesn't resolve to it after {} seconds",
+                name, ip, self.timeout_secs
+            );
+        }
+    }
+
+    async fn resolves_to(&self, name: &str, ip: IpAddr) -> bool {
+        // A fresh resolver each time, so an answer from before the change isn't cached
+        let resolver = match resolver(&self.resolvers) {
+            Ok(resolver) => resolver,
+            Err(e) => {
+                debug!("Couldn't check {}: {:#}", name, e);
+                return false;
+            }
+        };
+        let found: Result<Vec<IpAddr>, _> = match ip {
+            IpAddr::V4(_) => resolver
+                .ipv4_lookup(name)
+                .await
+                .map(|lookup| lookup.iter().copied().map(IpAddr::V4).collect()),
+            IpAddr::V6(_) => resolver
+                .ipv6_lookup(name)
+                .await
+                .map(|lookup| lookup.iter().copied().map(IpAddr::V6).collect()),
+        };
+        match found {
+            Ok(found) => {
+                debug!("{} resolves to {:?}", name, found);
+                found.contains(&ip)
+            }
+            Err(e) => {
+                debug!("Failed to resolve {}: {}", name, e);
+                false
+            }
+        }
+    }
+}
+
+/// Runs `check` until it passes, waiting `first_delay` after the first failure and twice as long
+/// after each one after that. Gives up once the next wait would go past `timeout`.
+async fn wait_until<F, Fut>(timeout: Duration, first_delay: Duration, mut check: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let start = tokio::time::Instant::now();
+    let mut delay = first_delay;
+    loop {
+        if check().await {
+            return true;
+        }
+        if start.elapsed() + delay > timeout {
+            return false;
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn checks_back_off_until_the_timeout() {
+        let mut checks = 0;
+        let seen = wait_until(Duration::from_secs(20), Duration::from_secs(2), || {
+            checks += 1;
+            async { false }
+        })
+        .await;
+
+        assert!(!seen);
+        // At 0, 2, 6 and 14 seconds, the next wait would end at 30
+        assert_eq!(checks, 4);
+
+        let mut checks = 0;
+        let seen = wait_until(Duration::from_secs(20), Duration::from_secs(2), || {
+            checks += 1;
+            let passed = checks == 2;
+            async move { passed }
+        })
+        .await;
+        assert!(seen);
+        assert_eq!(checks, 2);
+    }
+}
@@ -0,0 +1,127 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::update_dns::api::{UpdateOutcome, UpdateResult};
+
+/// How [crate::run_once] reports what happened to each record once the run is done.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    /// A line per record in the log.
+    #[default]
+    Text,
+    /// A JSON array on stdout, for scripts.
+    Json,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(format!(
+                "Unknown summary format {:?}, expected one of text, json",
+                s
+            )),
+        }
+    }
+}
+
+/// One record's line in the JSON summary.
+#[derive(Serialize, Debug)]
+pub(crate) struct SummaryEntry {
+    pub name: String,
+    /// `None` when the cache skipped the name before any provider saw it.
+    pub provider: Option<String>,
+    pub ip: IpAddr,
+    /// `cached`, `unchanged`, `created`, `updated`, `would_update` or `failed`.
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SummaryEntry {
+    pub(crate) fn cached(name: &str, ip: IpAddr) -> SummaryEntry {
+        SummaryEntry {
+            name: name.to_string(),
+            provider: None,
+            ip,
+            outcome: "cached",
+            error: None,
+        }
+    }
+
+    /// Not changed, as the name got a new address too recently.
+    pub(crate) fn held(name: &str, ip: IpAddr) -> SummaryEntry {
+        SummaryEntry {
+            name: name.to_string(),
+            provider: None,
+            ip,
+            outcome: "held",
+            error: None,
+        }
+    }
+
+    pub(crate) fn from_result(
+        name: &str,
+        provider: String,
+        ip: IpAddr,
+        result: &UpdateResult,
+    ) -> SummaryEntry {
+        let (outcome, error) = match result {
+            Ok(outcome) => (outcome_name(outcome), None),
+            Err(e) => ("failed", Some(format!("{:#}", e))),
+        };
+        SummaryEntry {
+            name: name.to_string(),
+            provider: Some(provider),
+            ip,
+            outcome,
+            error,
+        }
+    }
+}
+
+fn outcome_name(outcome: &UpdateOutcome) -> &'static str {
+    match (outcome.written, outcome.old_ip) {
+        (true, None) => "created",
+        (true, Some(_)) => "updated",
+        (false, Some(old_ip)) if old_ip == outcome.new_ip => "unchanged",
+        (false, _) => "would_update",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_serialize_with_an_error_only_on_failure() {
+        let ip: IpAddr = "203.0.113.5".parse().unwrap();
+        let entries = [
+            SummaryEntry::from_result(
+                "a.example.com",
+                "Stdout".to_string(),
+                ip,
+                &Ok(UpdateOutcome::written(None, ip)),
+            ),
+            SummaryEntry::from_result(
+                "b.example.com",
+                "Stdout".to_string(),
+                ip,
+                &Err(color_eyre::eyre::eyre!("refused").into()),
+            ),
+        ];
+
+        assert_eq!(
+            serde_json::to_value(&entries).unwrap(),
+            serde_json::json!([
+                {"name": "a.example.com", "provider": "Stdout", "ip": "203.0.113.5", "outcome": "created"},
+                {"name": "b.example.com", "provider": "Stdout", "ip": "203.0.113.5", "outcome": "failed", "error": "refused"},
+            ])
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::ffi::OsStr;
+
+use log::debug;
+
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// Tells systemd how the daemon is doing through `$NOTIFY_SOCKET`, for `Type=notify` and
+/// `WatchdogSec=`. Without the socket, or off Unix, every message is silently dropped.
+pub(crate) struct Systemd {
+    #[cfg(unix)]
+    socket: Option<unix::NotifySocket>,
+}
+
+impl Systemd {
+    pub(crate) fn from_env() -> Systemd {
+        match std::env::var_os(NOTIFY_SOCKET) {
+            Some(address) => Systemd::connect(&address),
+            None => Systemd::disabled(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn connect(address: &OsStr) -> Systemd {
+        let socket = unix::NotifySocket::connect(address)
+            .map_err(|e| {
+                debug!(
+                    "Not notifying systemd, {} is unusable: {}",
+                    NOTIFY_SOCKET, e
+                )
+            })
+            .ok();
+        Systemd { socket }
+    }
+
+    #[cfg(not(unix))]
+    fn connect(_address: &OsStr) -> Systemd {
+        Systemd::disabled()
+    }
+
+    fn disabled() -> Systemd {
+        Systemd {
+            #[cfg(unix)]
+            socket: None,
+        }
+    }
+
+    /// The daemon has started and is about to run its first update.
+    pub(crate) fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    /// An update went through, so the watchdog shouldn't restart us.
+    pub(crate) fn watchdog(&self) {
+        self.notify("WATCHDOG=1");
+    }
+
+    /// Failing to notify is only logged, as systemd will act on the missing message anyway.
+    fn notify(&self, state: &str) {
+        #[cfg(unix)]
+        if let Some(socket) = &self.socket {
+            if let Err(e) = socket.send(state) {
+                debug!("Failed to notify systemd of {}: {}", state, e);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = state;
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    pub(super) struct NotifySocket {
+        socket: UnixDatagram,
+    }
+
+    impl NotifySocket {
+        /// A leading `@` is a Linux abstract socket, anything else a path.
+        pub(super) fn connect(address: &OsStr) -> std::io::Result<NotifySocket> {
+            let socket = UnixDatagram::unbound()?;
+            match address.as_bytes().strip_prefix(b"@") {
+                #[cfg(target_os = "linux")]
+                Some(name) => {
+                    use std::os::linux::net::SocketAddrExt;
+
+                    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                    socket.connect_addr(&address)?;
+                }
+                _ => socket.connect(address)?,
+            }
+            Ok(NotifySocket { socket })
+        }
+
+        pub(super) fn send(&self, state: &str) -> std::io::Result<()> {
+            self.socket.send(state.as_bytes()).map(|_| ())
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::net::UnixDatagram;
+
+    use super::*;
+
+    #[test]
+    fn states_are_sent_to_the_socket() {
+        let path =
+            std::env::temp_dir().join(format!("box-dyn-dns-test-{}-notify", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+
+        let systemd = Systemd::connect(path.as_os_str());
+        systemd.ready();
+        systemd.watchdog();
+        let _ = std::fs::remove_file(&path);
+
+        let mut buf = [0; 32];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+    }
+
+    #[test]
+    fn missing_socket_is_ignored() {
+        let systemd = Systemd::connect(OsStr::new("/nonexistent/notify"));
+        systemd.ready();
+    }
+}
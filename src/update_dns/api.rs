@@ -1,18 +1,346 @@
-use std::net::Ipv4Addr;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
 
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use color_eyre::Report;
+use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 
-pub(crate) trait UpdateDnsCreator
+use crate::http::HttpError;
+
+pub trait UpdateDnsCreator
 where
     Self: UpdateDns,
 {
     type Config: DeserializeOwned;
+    /// The provider's key in the secrets file.
+    const TAG: &'static str;
+    /// One line for `box-dyn-dns providers`.
+    const DESCRIPTION: &'static str;
 
     fn from_config(config: Self::Config) -> Self;
 }
 
-pub(crate) trait UpdateDns {
+/// What happened to a record, for summaries and exit codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateOutcome {
+    /// The record's address before the update, `None` if there was no record or the provider
+    /// can't read it.
+    pub old_ip: Option<IpAddr>,
+    pub new_ip: IpAddr,
+    /// Whether a change was sent to the provider.
+    pub written: bool,
+    /// When the provider says the record was last modified, for providers that say.
+    pub modified_on: Option<ModifiedOn>,
+}
+
+/// A record's last-modified time as the provider reports it, so changes made by something else
+/// between runs can be noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModifiedOn {
+    /// Before this update, `None` if the record is new or the time isn't known.
+    pub found: Option<DateTime<FixedOffset>>,
+    /// After this update, the same as `found` if nothing was written.
+    pub now: Option<DateTime<FixedOffset>>,
+}
+
+impl UpdateOutcome {
+    /// The record already had `ip`.
+    pub fn unchanged(ip: IpAddr) -> UpdateOutcome {
+        UpdateOutcome {
+            old_ip: Some(ip),
+            new_ip: ip,
+            written: false,
+            modified_on: None,
+        }
+    }
+
+    /// A change was needed but not made, as in a dry run.
+    pub fn not_written(old_ip: Option<IpAddr>, new_ip: IpAddr) -> UpdateOutcome {
+        UpdateOutcome {
+            old_ip,
+            new_ip,
+            written: false,
+            modified_on: None,
+        }
+    }
+
+    pub fn written(old_ip: Option<IpAddr>, new_ip: IpAddr) -> UpdateOutcome {
+        UpdateOutcome {
+            old_ip,
+            new_ip,
+            written: true,
+            modified_on: None,
+        }
+    }
+
+    /// Leaves `modified_on` out if neither time is known.
+    pub fn with_modified_on(self, modified_on: ModifiedOn) -> UpdateOutcome {
+        UpdateOutcome {
+            modified_on: Some(modified_on)
+                .filter(|modified_on| *modified_on != ModifiedOn::default()),
+            ..self
+        }
+    }
+}
+
+impl Display for UpdateOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let change = match self.old_ip {
+            Some(old_ip) if old_ip == self.new_ip && !self.written => {
+                return write!(f, "already {}", self.new_ip)
+            }
+            Some(old_ip) => format!("{} -> {}", old_ip, self.new_ip),
+            None => format!("-> {}", self.new_ip),
+        };
+        if self.written {
+            write!(f, "updated {}", change)
+        } else {
+            write!(f, "not written {}", change)
+        }
+    }
+}
+
+/// The kind of record an address is written into.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    /// `A` for IPv4 addresses and `AAAA` for IPv6.
+    #[default]
+    #[serde(rename = "A", alias = "AAAA")]
+    Address,
+    /// The address as text, for other tools to read. Both families share the one record.
+    #[serde(rename = "TXT")]
+    Txt,
+    /// A hostname made from the address, for names that alias a host with a dynamic name. Both
+    /// families share the one record.
+    #[serde(rename = "CNAME")]
+    Cname,
+}
+
+impl RecordType {
+    pub fn name(self, ip: IpAddr) -> &'static str {
+        match (self, ip) {
+            (RecordType::Address, IpAddr::V4(_)) => "A",
+            (RecordType::Address, IpAddr::V6(_)) => "AAAA",
+            (RecordType::Txt, _) => "TXT",
+            (RecordType::Cname, _) => "CNAME",
+        }
+    }
+}
+
+/// Why an update failed, for callers that treat some failures differently, e.g. waiting on
+/// [RateLimited](UpdateDnsError::RateLimited) but alerting on [Auth](UpdateDnsError::Auth). Each
+/// kind keeps the whole report, and displays as it.
+pub enum UpdateDnsError {
+    /// The credentials were rejected or aren't allowed to make the change.
+    Auth(Report),
+    /// The zone or record doesn't exist.
+    NotFound(Report),
+    /// The provider wants fewer requests.
+    RateLimited(Report),
+    /// Any other failed request, whether there was no response or it had another error status.
+    Http(Report),
+    /// The provider's response couldn't be read.
+    Parse(Report),
+    Other(Report),
+}
+
+impl UpdateDnsError {
+    /// Picks the kind from the response's status.
+    pub fn from_status(status: StatusCode, report: Report) -> UpdateDnsError {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => UpdateDnsError::Auth(report),
+            StatusCode::NOT_FOUND => UpdateDnsError::NotFound(report),
+            StatusCode::TOO_MANY_REQUESTS => UpdateDnsError::RateLimited(report),
+            _ => UpdateDnsError::Http(report),
+        }
+    }
+
+    pub fn report(&self) -> &Report {
+        match self {
+            UpdateDnsError::Auth(report)
+            | UpdateDnsError::NotFound(report)
+            | UpdateDnsError::RateLimited(report)
+            | UpdateDnsError::Http(report)
+            | UpdateDnsError::Parse(report)
+            | UpdateDnsError::Other(report) => report,
+        }
+    }
+
+    pub fn into_report(self) -> Report {
+        match self {
+            UpdateDnsError::Auth(report)
+            | UpdateDnsError::NotFound(report)
+            | UpdateDnsError::RateLimited(report)
+            | UpdateDnsError::Http(report)
+            | UpdateDnsError::Parse(report)
+            | UpdateDnsError::Other(report) => report,
+        }
+    }
+
+    /// Makes an error of the same kind as this, for a report built on top of it.
+    pub(crate) fn kind(&self) -> fn(Report) -> UpdateDnsError {
+        match self {
+            UpdateDnsError::Auth(_) => UpdateDnsError::Auth,
+            UpdateDnsError::NotFound(_) => UpdateDnsError::NotFound,
+            UpdateDnsError::RateLimited(_) => UpdateDnsError::RateLimited,
+            UpdateDnsError::Http(_) => UpdateDnsError::Http,
+            UpdateDnsError::Parse(_) => UpdateDnsError::Parse,
+            UpdateDnsError::Other(_) => UpdateDnsError::Other,
+        }
+    }
+}
+
+/// Helpers return plain reports, so the kind is recovered from what's in the chain: an
+/// [UpdateDnsError] made further down, or the error that started it.
+impl From<Report> for UpdateDnsError {
+    fn from(report: Report) -> UpdateDnsError {
+        // Downcasting would also find one under added context, and drop the context
+        if report
+            .chain()
+            .next()
+            .is_some_and(|e| e.is::<UpdateDnsError>())
+        {
+            return report.downcast().expect("the report is an UpdateDnsError");
+        }
+        let mut kind: fn(Report) -> UpdateDnsError = UpdateDnsError::Other;
+        for cause in report.chain() {
+            if let Some(error) = cause.downcast_ref::<UpdateDnsError>() {
+                kind = error.kind();
+                break;
+            }
+            let parse = cause.is::<serde_json::Error>()
+                || cause.is::<quick_xml::DeError>()
+                || cause
+                    .downcast_ref::<reqwest::Error>()
+                    .is_some_and(reqwest::Error::is_decode);
+            if parse {
+                kind = UpdateDnsError::Parse;
+            } else if cause.is::<HttpError>() || cause.is::<reqwest::Error>() {
+                kind = UpdateDnsError::Http;
+            }
+        }
+        kind(report)
+    }
+}
+
+impl Display for UpdateDnsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            write!(f, "{:#}", self.report())
+        } else {
+            write!(f, "{}", self.report())
+        }
+    }
+}
+
+impl std::fmt::Debug for UpdateDnsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.report(), f)
+    }
+}
+
+/// The report's own causes, so wrapping this in another report reads the same as the original.
+impl std::error::Error for UpdateDnsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        let error: &(dyn std::error::Error + 'static) = self.report().as_ref();
+        error.source()
+    }
+}
+
+pub type UpdateResult = Result<UpdateOutcome, UpdateDnsError>;
+
+#[async_trait]
+pub trait UpdateDns: Send + Sync {
     fn describe(&self) -> String;
 
-    fn update_dns(&self, name: String, new_ip: Ipv4Addr) -> color_eyre::Result<()>;
+    /// What kind of record [UpdateDns::update_dns] writes.
+    fn record_type(&self) -> RecordType {
+        RecordType::Address
+    }
+
+    /// With `dry_run`, only reads are made and the change that would happen is logged.
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult;
+
+    /// Whether [update_many](UpdateDns::update_many) is overridden to do better than one name at a
+    /// time, so names should be handed over together rather than updated concurrently.
+    fn batches(&self) -> bool {
+        false
+    }
+
+    /// Updates several names to the same address, with the results in the order of `names`.
+    /// Providers that can do this in fewer requests than one name at a time override it.
+    async fn update_many(
+        &self,
+        names: &[String],
+        new_ip: IpAddr,
+        dry_run: bool,
+    ) -> Vec<UpdateResult> {
+        update_each(self, names, new_ip, dry_run).await
+    }
+
+    /// Confirms the credentials and settings work, without changing anything. `None` if the
+    /// provider has no read-only way to tell.
+    async fn check(&self) -> Option<color_eyre::Result<()>> {
+        None
+    }
+}
+
+/// The one-name-at-a-time [UpdateDns::update_many], for overrides to fall back on.
+pub async fn update_each<D: UpdateDns + ?Sized>(
+    dns: &D,
+    names: &[String],
+    new_ip: IpAddr,
+    dry_run: bool,
+) -> Vec<UpdateResult> {
+    let mut results = Vec::with_capacity(names.len());
+    for name in names {
+        results.push(dns.update_dns(name.clone(), new_ip, dry_run).await);
+    }
+    results
+}
+
+/// `name` relative to `zone`, as most provider APIs want it, with `apex` standing for the zone
+/// itself. Case and trailing dots don't matter. `None` if `name` isn't in the zone.
+pub(crate) fn relative_name(name: &str, zone: &str, apex: &str) -> Option<String> {
+    let name = name.trim_end_matches('.');
+    let zone = zone.trim_end_matches('.');
+    if name.eq_ignore_ascii_case(zone) {
+        return Some(apex.to_string());
+    }
+    let split = name.len().checked_sub(zone.len() + 1)?;
+    name.get(split..)?
+        .strip_prefix('.')
+        .filter(|suffix| suffix.eq_ignore_ascii_case(zone))
+        .map(|_| name[..split].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_made_relative_to_their_zone() {
+        assert_eq!(
+            relative_name("home.example.com", "example.com", "@").as_deref(),
+            Some("home")
+        );
+        assert_eq!(
+            relative_name("a.b.Example.COM.", "example.com.", "@").as_deref(),
+            Some("a.b")
+        );
+        assert_eq!(
+            relative_name("EXAMPLE.com", "example.com", "").as_deref(),
+            Some("")
+        );
+        assert_eq!(
+            relative_name("example.com", "example.com", "@").as_deref(),
+            Some("@")
+        );
+        assert_eq!(relative_name("notexample.com", "example.com", "@"), None);
+        assert_eq!(relative_name("home.example.org", "example.com", "@"), None);
+        assert_eq!(relative_name("com", "example.com", "@"), None);
+    }
 }
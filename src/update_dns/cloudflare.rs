@@ -1,117 +1,1279 @@
-use std::net::Ipv4Addr;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
-use attohttpc::Response;
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
 use color_eyre::eyre::{eyre, WrapErr};
-use log::info;
-use serde::{Deserialize, Serialize};
+use log::{debug, error, info, warn};
 
-use crate::update_dns::api::{UpdateDns, UpdateDnsCreator};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::config::Secret;
+use crate::env::resolve_env;
+use crate::http::{HttpClient, HttpOptions, HttpRequest, HttpResponse};
+use crate::update_dns::api::{
+    update_each, ModifiedOn, RecordType, UpdateDns, UpdateDnsCreator, UpdateDnsError,
+    UpdateOutcome, UpdateResult,
+};
+use crate::update_dns::retry::{send_with_retry, RetryConfig};
 
 pub struct Cloudflare {
     config: CloudflareConfig,
+    client: Box<dyn HttpClient>,
+    /// Looked up from `zone_name` the first time it's needed, if `zone_id` wasn't given.
+    resolved_zone_id: OnceCell<String>,
+    /// The zone's records, when `record_cache_secs` is set.
+    snapshot: Mutex<Option<ZoneSnapshot>>,
+    /// The zone's apex, set once `verify_token` has passed, so it's only checked on the first
+    /// update.
+    verified: OnceCell<String>,
 }
 
-#[derive(Deserialize, Debug)]
+struct ZoneSnapshot {
+    fetched: Instant,
+    records: Vec<CloudflareListDnsRecordRes>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct CloudflareConfig {
     #[serde(default = "default_base_url")]
     pub base_url: String,
+    /// May be a `${VAR}` placeholder, or left out to use `CLOUDFLARE_ZONE_ID` unless `zone_name` is
+    /// given.
+    #[serde(default)]
     pub zone_id: String,
-    pub api_token: String,
+    /// The zone's domain, to look up the zone id by instead. Also lets `dns_name`s be given
+    /// relative to the zone, e.g. `home` for `home.example.com`.
+    pub zone_name: Option<String>,
+    /// The record to update, when it isn't the `dns_name` itself. Takes precedence over every
+    /// `dns_name` given to this provider, which then only label the update in logs and history.
+    /// Written the same way as a `dns_name`.
+    pub record_name: Option<String>,
+    /// May be a `${VAR}` placeholder, or left out to use `CLOUDFLARE_API_TOKEN`.
+    #[serde(default)]
+    pub api_token: Secret<String>,
+    /// Create the record if there isn't one with the name yet, instead of failing.
+    #[serde(default)]
+    pub create_if_missing: bool,
+    /// When there's no record of the wanted type, delete any CNAME in the way and create the
+    /// record. Other types can sit alongside A, AAAA and TXT records, so they're left alone.
+    #[serde(default)]
+    pub replace_conflicting: bool,
+    /// TTL to set on the record, `1` is automatic. When absent, updates keep the existing TTL and
+    /// created records use automatic. Proxied records always get automatic.
+    #[serde(default, deserialize_with = "deserialize_ttl")]
+    pub ttl: Option<u32>,
+    /// Force Cloudflare's proxy on or off. When absent, updates keep the existing setting.
+    pub proxied: Option<bool>,
+    /// What to do when more than one record matches the name.
+    #[serde(default)]
+    pub multi_record: MultiRecord,
+    /// Refuse to touch a name that matches more records than this, in case a mistake in the
+    /// config would have `multi_record: update_all` rewrite a great many of them.
+    #[serde(default = "default_max_records")]
+    pub max_records: usize,
+    /// Tags every record this tool writes should have, as `name:value` or just `name`. Other tags
+    /// already on a record are kept.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Set on records this tool writes, with `{timestamp}` replaced by the time of the write. When
+    /// absent, updates keep the existing comment.
+    pub comment: Option<String>,
+    /// `A` to write the address itself, as `A` or `AAAA` by family, `TXT` to write it as text, or
+    /// `CNAME` to point the name at `cname_target`.
+    #[serde(default)]
+    pub record_type: RecordType,
+    /// What to put in a TXT record, with `{ip}` replaced by the address. Just the address if absent.
+    pub txt_content: Option<String>,
+    /// The hostname a CNAME record points at, with `{ip}` replaced by the address. Required with
+    /// `record_type: CNAME`.
+    pub cname_target: Option<String>,
+    /// Fetch the zone's whole record list once and reuse it for this many seconds, rather than a
+    /// request per name. Changes made outside this tool go unseen until it's fetched again.
+    pub record_cache_secs: Option<u64>,
+    /// Before the first update, check that the token is active and can edit the zone's DNS
+    /// records, rather than finding out from a 403 partway through. Names are then also checked to
+    /// be in the zone.
+    #[serde(default)]
+    pub verify_token: bool,
+    /// Several zones driven by the one token, each only given its own names. Replaces `zone_id`
+    /// and `zone_name` when set.
+    #[serde(default)]
+    pub zones: Vec<CloudflareZoneConfig>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CloudflareZoneConfig {
+    /// May be a `${VAR}` placeholder.
+    #[serde(default)]
+    pub zone_id: String,
+    pub zone_name: Option<String>,
+    /// The `dns_name`s in this zone, written the same way.
+    pub names: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiRecord {
+    /// Fail without changing anything.
+    #[default]
+    Error,
+    /// Point every matching record at the new IP.
+    UpdateAll,
+    /// Only update the first record Cloudflare returns.
+    First,
+}
+
+const TIMESTAMP_TOKEN: &str = "{timestamp}";
+const IP_TOKEN: &str = "{ip}";
+/// How Cloudflare lists the DNS:Edit permission on a zone.
+const DNS_EDIT_PERMISSION: &str = "#dns_records:edit";
+
+fn render_comment(template: &str) -> String {
+    template.replace(
+        TIMESTAMP_TOKEN,
+        &Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+}
+
+/// Whether a record of `record_type` holding `existing` already says `wanted`, ignoring differences
+/// DNS doesn't care about: how an address is written, case and a trailing dot in names, and the
+/// quotes around TXT content. TXT content is otherwise compared exactly, as it's case-sensitive,
+/// while CNAME targets are hostnames and compared ignoring case.
+fn content_matches(record_type: &str, existing: &str, wanted: &str) -> bool {
+    match record_type {
+        "A" | "AAAA" => match (existing.parse::<IpAddr>(), wanted.parse::<IpAddr>()) {
+            (Ok(existing), Ok(wanted)) => existing == wanted,
+            _ => existing == wanted,
+        },
+        "TXT" => existing.trim_matches('"') == wanted.trim_matches('"'),
+        _ => existing
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(wanted.trim_end_matches('.')),
+    }
+}
+
+/// Whether `comment` could have been rendered from `template` at any time, so that a record isn't
+/// rewritten just to bump its timestamp.
+fn comment_matches(template: &str, comment: &str) -> bool {
+    let mut parts = template.split(TIMESTAMP_TOKEN);
+    let mut rest = match comment.strip_prefix(parts.next().unwrap_or_default()) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(index) => rest = &rest[index + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
 }
 
 fn default_base_url() -> String {
     "https://api.cloudflare.com/client/v4".to_string()
 }
 
+fn default_max_records() -> usize {
+    5
+}
+
+impl CloudflareConfig {
+    pub(crate) fn resolve_env(&mut self) -> color_eyre::Result<()> {
+        if self.record_type == RecordType::Cname && self.cname_target.is_none() {
+            return Err(eyre!(
+                "`cloudflare.cname_target` is required with `record_type: CNAME`"
+            ));
+        }
+        for (index, zone) in self.zones.iter_mut().enumerate() {
+            if !zone.zone_id.is_empty() {
+                resolve_env(
+                    &mut zone.zone_id,
+                    &format!("cloudflare.zones[{}].zone_id", index),
+                    "CLOUDFLARE_ZONE_ID",
+                )?;
+            } else if zone.zone_name.is_none() {
+                return Err(eyre!(
+                    "`cloudflare.zones[{}]` needs a zone_id or zone_name",
+                    index
+                ));
+            }
+        }
+        if self.zones.is_empty() && !(self.zone_id.is_empty() && self.zone_name.is_some()) {
+            resolve_env(
+                &mut self.zone_id,
+                "cloudflare.zone_id",
+                "CLOUDFLARE_ZONE_ID",
+            )?;
+        }
+        resolve_env(
+            &mut self.api_token,
+            "cloudflare.api_token",
+            "CLOUDFLARE_API_TOKEN",
+        )
+    }
+}
+
+fn deserialize_ttl<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let ttl = Option::<u32>::deserialize(deserializer)?;
+    if let Some(ttl) = ttl {
+        check_ttl(ttl).map_err(D::Error::custom)?;
+    }
+    Ok(ttl)
+}
+
+pub(crate) fn check_ttl(ttl: u32) -> Result<(), String> {
+    match ttl {
+        1 | 60..=86400 => Ok(()),
+        _ => Err(format!(
+            "ttl must be 1 (automatic) or between 60 and 86400 seconds, got {}",
+            ttl
+        )),
+    }
+}
+
 impl Cloudflare {
-    fn create_cf_error(response: Response) -> color_eyre::Report {
+    pub(crate) fn with_client(config: CloudflareConfig, client: Box<dyn HttpClient>) -> Self {
+        Cloudflare {
+            config,
+            client,
+            resolved_zone_id: OnceCell::new(),
+            snapshot: Mutex::new(None),
+            verified: OnceCell::new(),
+        }
+    }
+
+    /// The zone as it was configured, for logs.
+    fn zone_label(&self) -> &str {
+        match &self.config.zone_name {
+            Some(zone_name) if self.config.zone_id.is_empty() => zone_name,
+            _ => &self.config.zone_id,
+        }
+    }
+
+    async fn zone_id(&self) -> color_eyre::Result<&str> {
+        match &self.config.zone_name {
+            Some(zone_name) if self.config.zone_id.is_empty() => self
+                .resolved_zone_id
+                .get_or_try_init(|| self.find_zone_id(zone_name))
+                .await
+                .map(String::as_str),
+            _ => Ok(&self.config.zone_id),
+        }
+    }
+
+    async fn find_zone_id(&self, zone_name: &str) -> color_eyre::Result<String> {
+        let request = HttpRequest::get(format!("{}/zones", self.config.base_url))
+            .query(&[("name", zone_name)])
+            .bearer_auth(self.config.api_token.as_str());
+        let response = send_with_retry(
+            &self.config.retry,
+            &*self.client,
+            &request,
+            Cloudflare::create_cf_error,
+        )
+        .await?;
+
+        let cf_res: CloudflareResponse<Vec<CloudflareZone>> = response.json()?;
+        let zones = cf_res.into_result()?;
+        match zones.as_slice() {
+            [zone] => {
+                info!("[cloudflare] Zone {} has id {}", zone.name, zone.id);
+                Ok(zone.id.clone())
+            }
+            [] => Err(eyre!("No Cloudflare zone is named {}", zone_name)),
+            _ => Err(eyre!(
+                "{} Cloudflare zones are named {}, set zone_id to pick one",
+                zones.len(),
+                zone_name
+            )),
+        }
+    }
+
+    /// Cloudflare only matches full names without the trailing dot. Like in a zone file, a trailing
+    /// dot marks a name as already full, `@` is the zone itself, and with a `zone_name` any other
+    /// name outside the zone is taken to be relative to it.
+    fn normalize_name(&self, name: &str) -> String {
+        let normalized = match (name.strip_suffix('.'), &self.config.zone_name) {
+            (Some(absolute), _) => absolute.to_string(),
+            (None, Some(zone)) => {
+                let zone = zone.trim_end_matches('.');
+                let lower = name.to_ascii_lowercase();
+                let zone_lower = zone.to_ascii_lowercase();
+                // The apex is exactly the zone, never a label under it
+                if name == "@" || lower == zone_lower {
+                    zone.to_string()
+                } else if lower.ends_with(&format!(".{}", zone_lower)) {
+                    name.to_string()
+                } else {
+                    format!("{}.{}", name, zone)
+                }
+            }
+            (None, None) => name.to_string(),
+        };
+        if normalized != name {
+            debug!("[cloudflare] Using {} for {}", normalized, name);
+        }
+        normalized
+    }
+
+    /// `record_name` if set, otherwise the `dns_name` the update is for.
+    fn record_name(&self, name: &str) -> String {
+        match &self.config.record_name {
+            Some(record_name) => {
+                debug!("[cloudflare] Updating {} for {}", record_name, name);
+                self.normalize_name(record_name)
+            }
+            None => self.normalize_name(name),
+        }
+    }
+
+    /// TXT content is quoted, as Cloudflare recommends.
+    fn record_content(&self, new_ip: IpAddr) -> String {
+        let render = |template: Option<&str>| {
+            template
+                .unwrap_or(IP_TOKEN)
+                .replace(IP_TOKEN, &new_ip.to_string())
+        };
+        match self.config.record_type {
+            RecordType::Address => new_ip.to_string(),
+            RecordType::Txt => format!("\"{}\"", render(self.config.txt_content.as_deref())),
+            RecordType::Cname => render(self.config.cname_target.as_deref()),
+        }
+    }
+
+    /// Cloudflare only accepts an automatic TTL (1) on proxied records, so a fixed `ttl` is dropped
+    /// for them rather than having the API refuse the write.
+    fn ttl_for(&self, ttl: u32, proxied: Option<bool>) -> u32 {
+        if proxied != Some(true) || ttl == 1 {
+            return ttl;
+        }
+        info!(
+            "[cloudflare] Using automatic TTL instead of {} for a proxied record, as Cloudflare requires",
+            ttl
+        );
+        1
+    }
+
+    /// Only address and CNAME records can go through Cloudflare's proxy.
+    fn proxied(&self, proxied: Option<bool>) -> Option<bool> {
+        match self.config.record_type {
+            RecordType::Address | RecordType::Cname => proxied,
+            RecordType::Txt => None,
+        }
+    }
+
+    fn create_cf_error(response: HttpResponse) -> color_eyre::Report {
         eyre!(
-            "{status} Error from Cloudflare: {de:?}",
-            status = response.status(),
+            "{status} Error from Cloudflare: {de}",
+            status = response.status,
             de = response.json::<CloudflareResponse<()>>().map_or_else(
                 |e| format!("Unable to read response: {:?}", e),
-                |v| format!("{:?}", v.errors),
+                |v| v
+                    .errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
             ),
         )
     }
-}
 
-impl UpdateDnsCreator for Cloudflare {
-    type Config = CloudflareConfig;
+    /// Reading the zone needs the same token and zone id as updating records in it.
+    async fn check_zone(&self) -> color_eyre::Result<()> {
+        let zone = self.fetch_zone().await?;
+        info!("[cloudflare] Zone {} is accessible", zone.name);
+        Ok(())
+    }
 
-    fn from_config(config: Self::Config) -> Self {
-        Cloudflare { config }
+    async fn fetch_zone(&self) -> color_eyre::Result<CloudflareZone> {
+        let request = HttpRequest::get(format!(
+            "{base}/zones/{zone_id}",
+            base = self.config.base_url,
+            zone_id = self.zone_id().await?,
+        ))
+        .bearer_auth(self.config.api_token.as_str());
+        let response = send_with_retry(
+            &self.config.retry,
+            &*self.client,
+            &request,
+            Cloudflare::create_cf_error,
+        )
+        .await?;
+
+        let cf_res: CloudflareResponse<CloudflareZone> = response.json()?;
+        cf_res.into_result()
     }
-}
 
-impl UpdateDns for Cloudflare {
-    fn describe(&self) -> String {
-        format!("Cloudflare[zone={zone_id}]", zone_id = &self.config.zone_id)
+    /// The zone's apex once the token is verified, `None` when `verify_token` is off.
+    async fn verify(&self) -> color_eyre::Result<Option<&str>> {
+        if !self.config.verify_token {
+            return Ok(None);
+        }
+        self.verified
+            .get_or_try_init(|| self.verify_token())
+            .await
+            .map(|zone| Some(zone.as_str()))
     }
 
-    fn update_dns(&self, name: String, new_ip: Ipv4Addr) -> color_eyre::Result<()> {
-        // GET all `name` `A` records
-        let response = attohttpc::get(format!(
+    /// A name outside the zone would otherwise just find no records, and fail as if it were missing.
+    fn check_in_zone(name: &str, zone: &str) -> color_eyre::Result<()> {
+        let name_lower = name.to_ascii_lowercase();
+        let zone_lower = zone.to_ascii_lowercase();
+        if name_lower == zone_lower || name_lower.ends_with(&format!(".{}", zone_lower)) {
+            Ok(())
+        } else {
+            Err(eyre!("Name {} is not in zone {}", name, zone))
+        }
+    }
+
+    /// A token that can read the zone but not edit its records would otherwise only fail on the
+    /// first write.
+    async fn verify_token(&self) -> color_eyre::Result<String> {
+        let request = HttpRequest::get(format!("{}/user/tokens/verify", self.config.base_url))
+            .bearer_auth(self.config.api_token.as_str());
+        let response = send_with_retry(
+            &self.config.retry,
+            &*self.client,
+            &request,
+            Cloudflare::create_cf_error,
+        )
+        .await
+        .wrap_err("Cloudflare token couldn't be verified")?;
+        let cf_res: CloudflareResponse<CloudflareTokenStatus> = response.json()?;
+        let token = cf_res.into_result()?;
+        if token.status != "active" {
+            return Err(eyre!("Cloudflare token is {}", token.status));
+        }
+
+        let zone = self
+            .fetch_zone()
+            .await
+            .wrap_err("Cloudflare token valid but can't read the zone")?;
+        // Cloudflare only lists permissions for some tokens, so an empty list proves nothing
+        if !zone.permissions.is_empty()
+            && !zone
+                .permissions
+                .iter()
+                .any(|permission| permission == DNS_EDIT_PERMISSION)
+        {
+            return Err(eyre!(
+                "Cloudflare token valid but missing DNS:Edit on zone {}",
+                zone.name
+            ));
+        }
+        info!(
+            "[cloudflare] Token is active and can edit zone {}",
+            zone.name
+        );
+        Ok(zone.name)
+    }
+
+    async fn create_record(
+        &self,
+        name: String,
+        record_type: &str,
+        new_ip: IpAddr,
+        dry_run: bool,
+    ) -> color_eyre::Result<UpdateOutcome> {
+        if dry_run {
+            info!(
+                "[cloudflare] Dry run, would create {} record {} with {}",
+                record_type, name, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(None, new_ip));
+        }
+
+        info!(
+            "[cloudflare] No existing {} record for {}, creating one.",
+            record_type, name
+        );
+
+        let proxied = self.proxied(self.config.proxied);
+        let body = CloudflareUpdateDnsRecordReq {
+            record_type: record_type.to_string(),
+            name,
+            content: self.record_content(new_ip),
+            ttl: self.ttl_for(self.config.ttl.unwrap_or(1), proxied),
+            proxied,
+            comment: self.config.comment.as_deref().map(render_comment),
+            tags: self.config.tags.clone(),
+        };
+        let request = HttpRequest::post(format!(
             "{base}/zones/{zone_id}/dns_records",
             base = self.config.base_url,
-            zone_id = &self.config.zone_id,
+            zone_id = self.zone_id().await?,
         ))
-        .param("name", &name)
-        .param("type", "A")
-        .header("Authorization", format!("Bearer {}", self.config.api_token))
-        .send()
-        .wrap_err("Failed to send request")?;
-        if !response.is_success() {
-            return Err(Cloudflare::create_cf_error(response));
+        .json(&body)?
+        .bearer_auth(self.config.api_token.as_str());
+        let response = send_with_retry(
+            &self.config.retry,
+            &*self.client,
+            &request,
+            Cloudflare::create_cf_error,
+        )
+        .await?;
+
+        let cf_res: CloudflareResponse<serde_json::Value> = response.json()?;
+        let result = cf_res.into_result()?;
+        info!("Successful: {:?}", result);
+        // Simpler to fetch again than to add the new record
+        *self.snapshot.lock().await = None;
+
+        Ok(
+            UpdateOutcome::written(None, new_ip).with_modified_on(ModifiedOn {
+                found: None,
+                now: written_modified_on(&result),
+            }),
+        )
+    }
+
+    /// The update `record` needs to end up with `new_ip` and the configured settings, `None` if it
+    /// already has them.
+    fn record_change(
+        &self,
+        record: &CloudflareListDnsRecordRes,
+        record_type: &str,
+        new_ip: IpAddr,
+    ) -> Option<CloudflareUpdateDnsRecordReq> {
+        let proxied = self.config.proxied.unwrap_or(record.proxied);
+        let ttl = self.ttl_for(
+            self.config.ttl.unwrap_or(record.ttl),
+            self.proxied(Some(proxied)),
+        );
+        let comment_matches = match &self.config.comment {
+            Some(template) => record
+                .comment
+                .as_deref()
+                .is_some_and(|comment| comment_matches(template, comment)),
+            None => true,
+        };
+        let missing_tags = self
+            .config
+            .tags
+            .iter()
+            .filter(|tag| !record.tags.contains(tag))
+            .collect::<Vec<_>>();
+        let content = self.record_content(new_ip);
+        if content_matches(record_type, &record.content, &content)
+            && record.ttl == ttl
+            // A TXT record is never sent a proxied setting, so whatever it has is kept
+            && record.proxied == self.proxied(Some(proxied)).unwrap_or(record.proxied)
+            && comment_matches
+            && missing_tags.is_empty()
+        {
+            info!(
+                "[cloudflare] New IP is the same as existing {} record, skipping update.",
+                record_type
+            );
+            return None;
         }
 
-        let cf_res: CloudflareResponse<Vec<CloudflareListDnsRecordRes>> =
-            response.json().wrap_err("Failed to read response")?;
-        assert!(
-            cf_res.success && cf_res.result.is_some(),
-            "Not successful or no result: {:?}",
-            cf_res
-        );
-        let list = cf_res.result.unwrap();
-        let record = match list.as_slice() {
-            [r] => r,
-            _ => return Err(eyre!("Expected exactly one result, got {:?}", list)),
+        info!(
+            "[cloudflare] Old {} content was {} with TTL {}, proxied {}",
+            record_type, record.content, record.ttl, record.proxied
+        );
+
+        Some(CloudflareUpdateDnsRecordReq {
+            record_type: record_type.to_string(),
+            name: record.name.to_string(),
+            content,
+            ttl,
+            proxied: self.proxied(Some(proxied)),
+            comment: self
+                .config
+                .comment
+                .as_deref()
+                .map(render_comment)
+                .or_else(|| record.comment.clone()),
+            // Sent even when none are configured, as a PUT would otherwise drop them
+            tags: record.tags.iter().chain(missing_tags).cloned().collect(),
+        })
+    }
+
+    async fn update_record(
+        &self,
+        record: &CloudflareListDnsRecordRes,
+        record_type: &str,
+        new_ip: IpAddr,
+        dry_run: bool,
+    ) -> color_eyre::Result<UpdateOutcome> {
+        let found = record.modified_on();
+        let unwritten = ModifiedOn { found, now: found };
+        let body = match self.record_change(record, record_type, new_ip) {
+            Some(body) => body,
+            None => return Ok(UpdateOutcome::unchanged(new_ip).with_modified_on(unwritten)),
         };
+        let old_ip = record.content.trim_matches('"').parse().ok();
 
-        if record.content == new_ip.to_string() {
-            info!("[cloudflare] New IP is the same as existing, skipping update.");
-            return Ok(());
+        if dry_run {
+            info!(
+                "[cloudflare] Dry run, would update {} from {} to {}",
+                record.name, record.content, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip).with_modified_on(unwritten));
         }
 
-        info!("[cloudflare] Old content was {}", record.content);
+        let now = self.put_record(&record.id, &body).await?;
+
+        Ok(UpdateOutcome::written(old_ip, new_ip).with_modified_on(ModifiedOn { found, now }))
+    }
 
-        let response = attohttpc::put(format!(
+    /// Returns when Cloudflare says the record was modified, if it says.
+    async fn put_record(
+        &self,
+        id: &str,
+        body: &CloudflareUpdateDnsRecordReq,
+    ) -> color_eyre::Result<Option<DateTime<FixedOffset>>> {
+        let request = HttpRequest::put(format!(
             "{base}/zones/{zone_id}/dns_records/{id}",
             base = self.config.base_url,
-            zone_id = &self.config.zone_id,
-            id = record.id,
+            zone_id = self.zone_id().await?,
+            id = id,
         ))
-        .json(&CloudflareUpdateDnsRecordReq {
-            record_type: "A".to_string(),
-            name: record.name.to_string(),
-            content: new_ip.to_string(),
-            ttl: record.ttl,
-        })
-        .wrap_err("Failed to serialize body")?
-        .header("Authorization", format!("Bearer {}", self.config.api_token))
-        .send()
-        .wrap_err("Failed to send request")?;
-        if !response.is_success() {
-            return Err(Cloudflare::create_cf_error(response));
+        .json(body)?
+        .bearer_auth(self.config.api_token.as_str());
+        let response = send_with_retry(
+            &self.config.retry,
+            &*self.client,
+            &request,
+            Cloudflare::create_cf_error,
+        )
+        .await?;
+
+        let cf_res: CloudflareResponse<serde_json::Value> = response.json()?;
+        let result = cf_res.into_result()?;
+        info!("Successful: {:?}", result);
+        let modified_on = written_modified_on(&result);
+        self.remember_change(id, body, modified_on).await;
+        Ok(modified_on)
+    }
+
+    /// Keeps the snapshot in line with a change this tool made.
+    async fn remember_change(
+        &self,
+        id: &str,
+        change: &CloudflareUpdateDnsRecordReq,
+        modified_on: Option<DateTime<FixedOffset>>,
+    ) {
+        if let Some(snapshot) = &mut *self.snapshot.lock().await {
+            if let Some(record) = snapshot.records.iter_mut().find(|record| record.id == id) {
+                record.content = change.content.clone();
+                record.ttl = change.ttl;
+                record.proxied = change.proxied.unwrap_or(record.proxied);
+                record.comment = change.comment.clone();
+                record.tags = change.tags.clone();
+                record.modified_on = modified_on.map(|modified_on| modified_on.to_rfc3339());
+            }
         }
+    }
 
-        let cf_res: CloudflareResponse<serde_json::Value> =
-            response.json().wrap_err("Failed to read response")?;
-        assert!(cf_res.success, "Not successful: {:?}", cf_res);
-        info!("Successful: {:?}", cf_res);
+    /// Sends every patch in one request, which either all apply or none do. Returns the patched
+    /// records as Cloudflare sent them back.
+    async fn batch_patch(
+        &self,
+        patches: Vec<CloudflareBatchPatch<'_>>,
+    ) -> color_eyre::Result<Vec<serde_json::Value>> {
+        let request = HttpRequest::post(format!(
+            "{base}/zones/{zone_id}/dns_records/batch",
+            base = self.config.base_url,
+            zone_id = self.zone_id().await?,
+        ))
+        .json(&CloudflareBatchReq { patches })?
+        .bearer_auth(self.config.api_token.as_str());
+        let response = send_with_retry(
+            &self.config.retry,
+            &*self.client,
+            &request,
+            Cloudflare::create_cf_error,
+        )
+        .await?;
+
+        let cf_res: CloudflareResponse<CloudflareBatchRes> = response.json()?;
+        Ok(cf_res.into_result()?.patches)
+    }
 
+    /// GETs all `name` records of `record_type`, from the snapshot if there is one.
+    async fn list_records(
+        &self,
+        name: &str,
+        record_type: &str,
+    ) -> color_eyre::Result<Vec<CloudflareListDnsRecordRes>> {
+        let records = self.list_matching(name, Some(record_type)).await?;
+        if records.len() > self.config.max_records {
+            return Err(eyre!(
+                "{} {} records match {}, more than max_records ({}) allows. Narrow the name or raise max_records",
+                records.len(),
+                record_type,
+                name,
+                self.config.max_records
+            ));
+        }
+        Ok(records)
+    }
+
+    /// `name`'s records of `record_type`, or of every type if it's `None`.
+    async fn list_matching(
+        &self,
+        name: &str,
+        record_type: Option<&str>,
+    ) -> color_eyre::Result<Vec<CloudflareListDnsRecordRes>> {
+        if let Some(secs) = self.config.record_cache_secs {
+            let mut snapshot = self.snapshot.lock().await;
+            let records = match &*snapshot {
+                Some(snapshot) if snapshot.fetched.elapsed() < Duration::from_secs(secs) => {
+                    &snapshot.records
+                }
+                _ => {
+                    let fetched = ZoneSnapshot {
+                        fetched: Instant::now(),
+                        records: self.list_zone_records().await?,
+                    };
+                    &snapshot.insert(fetched).records
+                }
+            };
+            let name = name.trim_end_matches('.');
+            return Ok(records
+                .iter()
+                .filter(|record| {
+                    record.name.eq_ignore_ascii_case(name)
+                        && record_type.is_none_or(|wanted| record.record_type == wanted)
+                })
+                .cloned()
+                .collect());
+        }
+
+        match record_type {
+            Some(record_type) => {
+                self.list_pages(&[("name", name), ("type", record_type)])
+                    .await
+            }
+            None => self.list_pages(&[("name", name)]).await,
+        }
+    }
+
+    /// Clears records at `name` out of the way of a new `record_type` record: a CNAME, or for a
+    /// CNAME everything else, as a CNAME can't share its name with any other record.
+    async fn delete_conflicting(
+        &self,
+        name: &str,
+        record_type: &str,
+        dry_run: bool,
+    ) -> color_eyre::Result<()> {
+        let conflicting = if record_type == "CNAME" {
+            let conflicting: Vec<_> = self
+                .list_matching(name, None)
+                .await?
+                .into_iter()
+                .filter(|record| record.record_type != "CNAME")
+                .collect();
+            if conflicting.len() > self.config.max_records {
+                return Err(eyre!(
+                    "{} records are in the way of a CNAME for {}, more than max_records ({}) allows",
+                    conflicting.len(),
+                    name,
+                    self.config.max_records
+                ));
+            }
+            conflicting
+        } else {
+            self.list_records(name, "CNAME").await?
+        };
+        for record in conflicting {
+            if dry_run {
+                warn!(
+                    "[cloudflare] Dry run, would delete {} record {} ({} -> {}) to make way for a {} record",
+                    record.record_type, record.id, record.name, record.content, record_type
+                );
+                continue;
+            }
+            warn!(
+                "[cloudflare] Deleting {} record {} ({} -> {}) to make way for a {} record",
+                record.record_type, record.id, record.name, record.content, record_type
+            );
+            let request = HttpRequest::delete(format!(
+                "{base}/zones/{zone_id}/dns_records/{id}",
+                base = self.config.base_url,
+                zone_id = self.zone_id().await?,
+                id = record.id,
+            ))
+            .bearer_auth(self.config.api_token.as_str());
+            let response = send_with_retry(
+                &self.config.retry,
+                &*self.client,
+                &request,
+                Cloudflare::create_cf_error,
+            )
+            .await?;
+
+            let cf_res: CloudflareResponse<serde_json::Value> = response.json()?;
+            cf_res.into_result()?;
+            *self.snapshot.lock().await = None;
+        }
         Ok(())
     }
+
+    /// Every record in the zone.
+    async fn list_zone_records(&self) -> color_eyre::Result<Vec<CloudflareListDnsRecordRes>> {
+        let records = self.list_pages(&[("per_page", "5000")]).await?;
+        info!("[cloudflare] Fetched {} records in the zone", records.len());
+        Ok(records)
+    }
+
+    /// The records matching `query`, following `result_info` through every page of them.
+    async fn list_pages(
+        &self,
+        query: &[(&str, &str)],
+    ) -> color_eyre::Result<Vec<CloudflareListDnsRecordRes>> {
+        let mut records = Vec::new();
+        let mut page = 1;
+        loop {
+            let mut request = HttpRequest::get(format!(
+                "{base}/zones/{zone_id}/dns_records",
+                base = self.config.base_url,
+                zone_id = self.zone_id().await?,
+            ))
+            .query(query)
+            .bearer_auth(self.config.api_token.as_str());
+            // The first page is what's sent without one
+            if page > 1 {
+                request = request.query(&[("page", &page.to_string())]);
+            }
+            let response = send_with_retry(
+                &self.config.retry,
+                &*self.client,
+                &request,
+                Cloudflare::create_cf_error,
+            )
+            .await?;
+
+            let cf_res: CloudflareResponse<Vec<CloudflareListDnsRecordRes>> = response.json()?;
+            let total_pages = cf_res
+                .result_info
+                .as_ref()
+                .map_or(1, |info| info.total_pages);
+            records.extend(cf_res.into_result()?);
+            if page >= total_pages {
+                break;
+            }
+            page += 1;
+        }
+        Ok(records)
+    }
+
+    /// Makes `name` hold `new_ip`, whatever records it has now.
+    async fn upsert_record(
+        &self,
+        name: String,
+        record_type: &str,
+        new_ip: IpAddr,
+        dry_run: bool,
+    ) -> color_eyre::Result<UpdateOutcome> {
+        let list = self.list_records(&name, record_type).await?;
+        self.upsert_listed(name, list, record_type, new_ip, dry_run)
+            .await
+    }
+
+    /// Given the records `name` has of `record_type`: none are created if allowed, one is updated
+    /// if it differs and skipped if not, and several are handled as `multi_record` says.
+    async fn upsert_listed(
+        &self,
+        name: String,
+        list: Vec<CloudflareListDnsRecordRes>,
+        record_type: &str,
+        new_ip: IpAddr,
+        dry_run: bool,
+    ) -> color_eyre::Result<UpdateOutcome> {
+        match (list.as_slice(), self.config.multi_record) {
+            ([], _) if self.config.replace_conflicting => {
+                self.delete_conflicting(&name, record_type, dry_run).await?;
+                self.create_record(name, record_type, new_ip, dry_run).await
+            }
+            ([], _) if self.config.create_if_missing => {
+                self.create_record(name, record_type, new_ip, dry_run).await
+            }
+            ([], _) => Err(eyre!(
+                "No {} record for {}, set create_if_missing to create one",
+                record_type,
+                name
+            )),
+            ([record], _) | ([record, ..], MultiRecord::First) => {
+                self.update_record(record, record_type, new_ip, dry_run)
+                    .await
+            }
+            ([_, _, ..], MultiRecord::UpdateAll) => {
+                let mut outcome = UpdateOutcome::unchanged(new_ip);
+                let mut failed = 0;
+                for record in &list {
+                    match self
+                        .update_record(record, record_type, new_ip, dry_run)
+                        .await
+                    {
+                        // The records all end up with the same address, so any change stands for them
+                        Ok(record_outcome)
+                            if record_outcome.written || record_outcome.old_ip != Some(new_ip) =>
+                        {
+                            // Which record's modification time it would be is anyone's guess
+                            outcome = UpdateOutcome {
+                                modified_on: None,
+                                ..record_outcome
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(
+                                "[cloudflare] Failed to update {} record {}: {:?}",
+                                record_type, record.id, e
+                            );
+                            failed += 1;
+                        }
+                    }
+                }
+                if failed > 0 {
+                    return Err(eyre!(
+                        "Failed to update {} of {} {} records for {}",
+                        failed,
+                        list.len(),
+                        record_type,
+                        name
+                    ));
+                }
+                Ok(outcome)
+            }
+            (_, MultiRecord::Error) => Err(eyre!(
+                "{} {} records for {}, set multi_record to pick which to update: {:?}",
+                list.len(),
+                record_type,
+                name,
+                list
+            )),
+        }
+    }
+}
+
+impl UpdateDnsCreator for Cloudflare {
+    type Config = CloudflareConfig;
+    const TAG: &'static str = "cloudflare";
+    const DESCRIPTION: &'static str = "Cloudflare, by zone id or name, or several zones";
+
+    fn from_config(config: Self::Config) -> Self {
+        let client = Box::new(config.http.client());
+        Cloudflare::with_client(config, client)
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Cloudflare {
+    fn describe(&self) -> String {
+        format!("Cloudflare[zone={zone}]", zone = self.zone_label())
+    }
+
+    fn record_type(&self) -> RecordType {
+        self.config.record_type
+    }
+
+    fn batches(&self) -> bool {
+        true
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let zone = self.verify().await?;
+        let record_type = self.config.record_type.name(new_ip);
+        let name = self.record_name(&name);
+        if let Some(zone) = zone {
+            Cloudflare::check_in_zone(&name, zone)?;
+        }
+        Ok(self
+            .upsert_record(name, record_type, new_ip, dry_run)
+            .await?)
+    }
+
+    /// Names with a single record to change are written in one batch request. Anything else, or
+    /// everything if the batch fails, goes through the per-record path.
+    async fn update_many(
+        &self,
+        names: &[String],
+        new_ip: IpAddr,
+        dry_run: bool,
+    ) -> Vec<UpdateResult> {
+        if names.len() < 2 || dry_run {
+            return update_each(self, names, new_ip, dry_run).await;
+        }
+        let zone = match self.verify().await {
+            Ok(zone) => zone,
+            Err(e) => {
+                let error = UpdateDnsError::from(e);
+                let (kind, message) = (error.kind(), format!("{:#}", error));
+                return names
+                    .iter()
+                    .map(|_| Err(kind(eyre!("{}", message))))
+                    .collect();
+            }
+        };
+        let record_type = self.config.record_type.name(new_ip);
+        let names: Vec<String> = names.iter().map(|name| self.record_name(name)).collect();
+
+        let mut results: Vec<Option<UpdateResult>> = names.iter().map(|_| None).collect();
+        // Names that share a record are written to it once
+        let mut pending: Vec<(
+            Vec<usize>,
+            CloudflareListDnsRecordRes,
+            CloudflareUpdateDnsRecordReq,
+        )> = Vec::new();
+        for (index, name) in names.iter().enumerate() {
+            if let Some(Err(e)) = zone.map(|zone| Cloudflare::check_in_zone(name, zone)) {
+                results[index] = Some(Err(e.into()));
+                continue;
+            }
+            let list = match self.list_records(name, record_type).await {
+                Ok(list) => list,
+                Err(e) => {
+                    results[index] = Some(Err(e.into()));
+                    continue;
+                }
+            };
+            match (list.as_slice(), self.config.multi_record) {
+                ([record], _) | ([record, ..], MultiRecord::First) => {
+                    match self.record_change(record, record_type, new_ip) {
+                        Some(change) => match pending
+                            .iter_mut()
+                            .find(|(_, pending, _)| pending.id == record.id)
+                        {
+                            Some((indices, _, _)) => indices.push(index),
+                            None => pending.push((vec![index], record.clone(), change)),
+                        },
+                        None => {
+                            let found = record.modified_on();
+                            results[index] = Some(Ok(UpdateOutcome::unchanged(new_ip)
+                                .with_modified_on(ModifiedOn { found, now: found })))
+                        }
+                    }
+                }
+                _ => {
+                    results[index] = Some(
+                        self.upsert_listed(name.clone(), list, record_type, new_ip, dry_run)
+                            .await
+                            .map_err(UpdateDnsError::from),
+                    )
+                }
+            }
+        }
+
+        // The records as the batch sent them back, `None` if there was no batch or it failed
+        let batched = if pending.len() < 2 {
+            None
+        } else {
+            let patches = pending
+                .iter()
+                .map(|(_, record, change)| CloudflareBatchPatch {
+                    id: &record.id,
+                    record: change,
+                })
+                .collect();
+            match self.batch_patch(patches).await {
+                Ok(written) => {
+                    info!("Successful: updated {} records in one batch", pending.len());
+                    for (_, record, change) in &pending {
+                        self.remember_change(
+                            &record.id,
+                            change,
+                            batch_modified_on(&written, &record.id),
+                        )
+                        .await;
+                    }
+                    Some(written)
+                }
+                Err(e) => {
+                    warn!(
+                        "[cloudflare] Batch update failed, updating records one at a time: {:#}",
+                        e
+                    );
+                    None
+                }
+            }
+        };
+        for (indices, record, change) in pending {
+            let old_ip = record.content.trim_matches('"').parse().ok();
+            let found = record.modified_on();
+            let now = match &batched {
+                Some(written) => Ok(batch_modified_on(written, &record.id)),
+                None => self.put_record(&record.id, &change).await,
+            };
+            let result = now
+                .map(|now| {
+                    UpdateOutcome::written(old_ip, new_ip)
+                        .with_modified_on(ModifiedOn { found, now })
+                })
+                .map_err(UpdateDnsError::from);
+            for &index in &indices[1..] {
+                results[index] = Some(match &result {
+                    Ok(outcome) => Ok(*outcome),
+                    Err(e) => Err(e.kind()(eyre!("{:#}", e))),
+                });
+            }
+            results[indices[0]] = Some(result);
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("every name has a result"))
+            .collect()
+    }
+
+    async fn check(&self) -> Option<color_eyre::Result<()>> {
+        if let Err(e) = self.verify().await {
+            return Some(Err(e));
+        }
+        Some(self.check_zone().await)
+    }
+}
+
+/// Cloudflare with `zones` set: one [Cloudflare] per zone, each only given the names listed for it.
+pub struct CloudflareZones {
+    zones: Vec<(Vec<String>, Cloudflare)>,
+}
+
+impl CloudflareZones {
+    pub(crate) fn with_clients(
+        mut config: CloudflareConfig,
+        client: impl Fn(&CloudflareConfig) -> Box<dyn HttpClient>,
+    ) -> Self {
+        let zones = std::mem::take(&mut config.zones)
+            .into_iter()
+            .map(|zone| {
+                let mut zone_config = config.clone();
+                zone_config.zone_id = zone.zone_id;
+                zone_config.zone_name = zone.zone_name;
+                let client = client(&zone_config);
+                (zone.names, Cloudflare::with_client(zone_config, client))
+            })
+            .collect();
+        CloudflareZones { zones }
+    }
+
+    fn zone_for(&self, name: &str) -> Option<&Cloudflare> {
+        let name = name.trim_end_matches('.');
+        self.zones
+            .iter()
+            .find(|(names, _)| {
+                names
+                    .iter()
+                    .any(|listed| listed.trim_end_matches('.').eq_ignore_ascii_case(name))
+            })
+            .map(|(_, cloudflare)| cloudflare)
+    }
+
+    fn not_in_any_zone(name: &str) -> color_eyre::Report {
+        eyre!("{} isn't listed in any of the Cloudflare zones", name)
+    }
+}
+
+impl UpdateDnsCreator for CloudflareZones {
+    type Config = CloudflareConfig;
+    const TAG: &'static str = Cloudflare::TAG;
+    const DESCRIPTION: &'static str = Cloudflare::DESCRIPTION;
+
+    fn from_config(config: Self::Config) -> Self {
+        CloudflareZones::with_clients(config, |config| Box::new(config.http.client()))
+    }
+}
+
+#[async_trait]
+impl UpdateDns for CloudflareZones {
+    fn describe(&self) -> String {
+        format!(
+            "Cloudflare[zones={zones}]",
+            zones = self
+                .zones
+                .iter()
+                .map(|(_, cloudflare)| cloudflare.zone_label())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn record_type(&self) -> RecordType {
+        self.zones
+            .first()
+            .map_or_else(RecordType::default, |(_, cloudflare)| {
+                cloudflare.record_type()
+            })
+    }
+
+    fn batches(&self) -> bool {
+        true
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        match self.zone_for(&name) {
+            Some(cloudflare) => cloudflare.update_dns(name, new_ip, dry_run).await,
+            None => Err(CloudflareZones::not_in_any_zone(&name).into()),
+        }
+    }
+
+    /// Each zone gets its own names in one go, so they can still be batched.
+    async fn update_many(
+        &self,
+        names: &[String],
+        new_ip: IpAddr,
+        dry_run: bool,
+    ) -> Vec<UpdateResult> {
+        let mut results: Vec<Option<UpdateResult>> = names.iter().map(|_| None).collect();
+        for (_, cloudflare) in &self.zones {
+            let (indices, zone_names): (Vec<usize>, Vec<String>) = names
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| {
+                    self.zone_for(name)
+                        .map_or(false, |zone| std::ptr::eq(zone, cloudflare))
+                })
+                .map(|(index, name)| (index, name.clone()))
+                .unzip();
+            if zone_names.is_empty() {
+                continue;
+            }
+            let zone_results = cloudflare.update_many(&zone_names, new_ip, dry_run).await;
+            for (index, result) in indices.into_iter().zip(zone_results) {
+                results[index] = Some(result);
+            }
+        }
+
+        results
+            .into_iter()
+            .zip(names)
+            .map(|(result, name)| {
+                result.unwrap_or_else(|| Err(CloudflareZones::not_in_any_zone(name).into()))
+            })
+            .collect()
+    }
+
+    async fn check(&self) -> Option<color_eyre::Result<()>> {
+        for (_, cloudflare) in &self.zones {
+            if let Some(Err(e)) = cloudflare.check().await {
+                return Some(Err(e.wrap_err(format!(
+                    "Zone {} failed the check",
+                    cloudflare.zone_label()
+                ))));
+            }
+        }
+        Some(Ok(()))
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -120,6 +1282,37 @@ struct CloudflareResponse<T> {
     result: Option<T>,
     success: bool,
     errors: Vec<CloudflareError>,
+    /// Only on lists.
+    result_info: Option<CloudflareResultInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CloudflareResultInfo {
+    total_pages: u32,
+}
+
+impl<T> CloudflareResponse<T> {
+    /// Cloudflare can report failure in the body of an otherwise successful response.
+    fn into_result(self) -> color_eyre::Result<T> {
+        match self {
+            CloudflareResponse {
+                success: true,
+                result: Some(result),
+                ..
+            } => Ok(result),
+            CloudflareResponse {
+                success, errors, ..
+            } => Err(eyre!(
+                "Cloudflare response was not successful (success={}): {}",
+                success,
+                errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -128,12 +1321,65 @@ struct CloudflareError {
     message: String,
 }
 
+impl Display for CloudflareError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.code, self.message)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct CloudflareZone {
+    id: String,
+    name: String,
+    #[serde(default)]
+    permissions: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
+struct CloudflareTokenStatus {
+    status: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 struct CloudflareListDnsRecordRes {
     id: String,
     name: String,
+    #[serde(rename = "type", default)]
+    record_type: String,
     content: String,
     ttl: u32,
+    #[serde(default)]
+    proxied: bool,
+    comment: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    modified_on: Option<String>,
+}
+
+impl CloudflareListDnsRecordRes {
+    fn modified_on(&self) -> Option<DateTime<FixedOffset>> {
+        parse_modified_on(self.modified_on.as_deref())
+    }
+}
+
+fn parse_modified_on(modified_on: Option<&str>) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(modified_on?).ok()
+}
+
+/// The `modified_on` of a record Cloudflare sent back after writing it.
+fn written_modified_on(record: &serde_json::Value) -> Option<DateTime<FixedOffset>> {
+    parse_modified_on(
+        record
+            .get("modified_on")
+            .and_then(serde_json::Value::as_str),
+    )
+}
+
+fn batch_modified_on(written: &[serde_json::Value], id: &str) -> Option<DateTime<FixedOffset>> {
+    written
+        .iter()
+        .find(|record| record.get("id").and_then(serde_json::Value::as_str) == Some(id))
+        .and_then(written_modified_on)
 }
 
 #[derive(Serialize)]
@@ -143,4 +1389,1146 @@ struct CloudflareUpdateDnsRecordReq {
     name: String,
     content: String,
     ttl: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxied: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CloudflareBatchReq<'a> {
+    patches: Vec<CloudflareBatchPatch<'a>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CloudflareBatchRes {
+    #[serde(default)]
+    patches: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct CloudflareBatchPatch<'a> {
+    id: &'a str,
+    #[serde(flatten)]
+    record: &'a CloudflareUpdateDnsRecordReq,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::http::mock::MockHttpClient;
+
+    const LIST_RES: &str = r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "198.51.100.1", "ttl": 300, "proxied": false}]}"#;
+
+    fn mock_cloudflare(client: MockHttpClient) -> (Cloudflare, std::sync::Arc<MockHttpClient>) {
+        mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nretry:\n  max_retries: 0",
+            client,
+        )
+    }
+
+    fn mock_cloudflare_with(
+        config: &str,
+        client: MockHttpClient,
+    ) -> (Cloudflare, std::sync::Arc<MockHttpClient>) {
+        let client = std::sync::Arc::new(client);
+        let config: CloudflareConfig = serde_yaml::from_str(config).unwrap();
+        (
+            Cloudflare::with_client(config, Box::new(client.clone())),
+            client,
+        )
+    }
+
+    #[tokio::test]
+    async fn unchanged_ip_is_not_written() {
+        let (cloudflare, client) =
+            mock_cloudflare(MockHttpClient::default().respond(200, LIST_RES));
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "198.51.100.1".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(!outcome.written);
+        let requests = client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, reqwest::Method::GET);
+    }
+
+    #[tokio::test]
+    async fn changed_ip_is_put_once() {
+        let (cloudflare, client) = mock_cloudflare(
+            MockHttpClient::default()
+                .respond(200, LIST_RES)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(outcome.written);
+        assert_eq!(outcome.old_ip, Some("198.51.100.1".parse().unwrap()));
+        let requests = client.requests();
+        assert_eq!(requests.len(), 2);
+        let put = &requests[1];
+        assert_eq!(put.method, reqwest::Method::PUT);
+        assert_eq!(put.url, "https://cf.test/zones/z/dns_records/rec1");
+        let body: serde_json::Value = serde_json::from_slice(put.body.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "A",
+                "name": "a.example.com",
+                "content": "203.0.113.5",
+                "ttl": 300,
+                "proxied": false,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn proxied_record_gets_automatic_ttl() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nttl: 300\nproxied: true",
+            MockHttpClient::default()
+                .respond(200, LIST_RES)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "198.51.100.1".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        let body: serde_json::Value =
+            serde_json::from_slice(client.requests()[1].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body["ttl"], 1);
+        assert_eq!(body["proxied"], true);
+    }
+
+    const EMPTY_LIST_RES: &str = r#"{"success": true, "errors": [], "result": []}"#;
+
+    #[tokio::test]
+    async fn upsert_creates_only_when_allowed() {
+        let (cloudflare, client) =
+            mock_cloudflare(MockHttpClient::default().respond(200, EMPTY_LIST_RES));
+        let error = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No A record for a.example.com, set create_if_missing to create one"
+        );
+        assert_eq!(client.requests().len(), 1);
+
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\ncreate_if_missing: true",
+            MockHttpClient::default()
+                .respond(200, EMPTY_LIST_RES)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            UpdateOutcome::written(None, "203.0.113.5".parse().unwrap())
+        );
+        let requests = client.requests();
+        assert_eq!(requests[1].method, reqwest::Method::POST);
+        assert_eq!(requests[1].url, "https://cf.test/zones/z/dns_records");
+    }
+
+    #[tokio::test]
+    async fn apex_record_is_listed_and_created_by_the_zone_name() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\nzone_name: example.com\napi_token: t\ncreate_if_missing: true",
+            MockHttpClient::default()
+                .respond(200, EMPTY_LIST_RES)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        cloudflare
+            .update_dns("@".to_string(), "203.0.113.5".parse().unwrap(), false)
+            .await
+            .unwrap();
+
+        let requests = client.requests();
+        assert!(requests[0]
+            .query
+            .contains(&("name".to_string(), "example.com".to_string())));
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[1].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body["name"], "example.com");
+    }
+
+    #[tokio::test]
+    async fn names_matching_too_many_records_are_refused() {
+        let record =
+            r#"{"id": "rec", "name": "a.example.com", "content": "198.51.100.1", "ttl": 300}"#;
+        let list = format!(
+            r#"{{"success": true, "errors": [], "result": [{}]}}"#,
+            [record; 3].join(", ")
+        );
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nmulti_record: update_all\nmax_records: 2",
+            MockHttpClient::default().respond(200, &list),
+        );
+
+        let error = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "3 A records match a.example.com, more than max_records (2) allows. Narrow the name or raise max_records"
+        );
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn every_page_of_records_is_listed() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nmulti_record: update_all",
+            MockHttpClient::default()
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result_info": {"total_pages": 2}, "result": [{"id": "rec1", "name": "a.example.com", "content": "203.0.113.5", "ttl": 300}]}"#,
+                )
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result_info": {"total_pages": 2}, "result": [{"id": "rec2", "name": "a.example.com", "content": "198.51.100.2", "ttl": 300}]}"#,
+                )
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(outcome.written);
+        let requests = client.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(!requests[0].query.iter().any(|(name, _)| name == "page"));
+        assert!(requests[1]
+            .query
+            .contains(&("page".to_string(), "2".to_string())));
+        // Only the record from the second page needed changing
+        assert!(
+            requests[2].url.ends_with("/dns_records/rec2"),
+            "{}",
+            requests[2].url
+        );
+    }
+
+    #[tokio::test]
+    async fn upsert_refuses_several_records_by_default() {
+        let (cloudflare, client) = mock_cloudflare(MockHttpClient::default().respond(
+            200,
+            r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "198.51.100.1", "ttl": 300}, {"id": "rec2", "name": "a.example.com", "content": "198.51.100.2", "ttl": 300}]}"#,
+        ));
+
+        let error = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .starts_with("2 A records for a.example.com, set multi_record"),
+            "{}",
+            error
+        );
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn comment_is_stamped_on_update() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\ncomment: managed, updated {timestamp}",
+            MockHttpClient::default()
+                .respond(200, LIST_RES)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        let body: serde_json::Value =
+            serde_json::from_slice(client.requests()[1].body.as_ref().unwrap()).unwrap();
+        let comment = body["comment"].as_str().unwrap();
+        assert!(comment.starts_with("managed, updated 20"), "{}", comment);
+        assert!(comment_matches("managed, updated {timestamp}", comment));
+    }
+
+    #[tokio::test]
+    async fn missing_tag_is_added_alongside_existing_ones() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\ntags: [managed:box-dyn-dns, home]",
+            MockHttpClient::default()
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "198.51.100.1", "ttl": 300, "proxied": false, "tags": ["home", "owner:me"]}]}"#,
+                )
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "198.51.100.1".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(outcome.written);
+        let body: serde_json::Value =
+            serde_json::from_slice(client.requests()[1].body.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            body["tags"],
+            serde_json::json!(["home", "owner:me", "managed:box-dyn-dns"])
+        );
+    }
+
+    #[tokio::test]
+    async fn older_timestamp_in_comment_is_not_rewritten() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\ncomment: managed, updated {timestamp}",
+            MockHttpClient::default().respond(
+                200,
+                r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "198.51.100.1", "ttl": 300, "proxied": false, "comment": "managed, updated 2020-01-01T00:00:00Z"}]}"#,
+            ),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "198.51.100.1".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(!outcome.written);
+        assert_eq!(client.requests().len(), 1);
+        assert!(!comment_matches(
+            "managed, updated {timestamp}",
+            "edited by hand"
+        ));
+    }
+
+    #[tokio::test]
+    async fn txt_record_gets_quoted_content() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nrecord_type: TXT\ntxt_content: ip={ip}",
+            MockHttpClient::default()
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "\"ip=198.51.100.1\"", "ttl": 300}]}"#,
+                )
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        let requests = client.requests();
+        assert!(requests[0]
+            .query
+            .contains(&("type".to_string(), "TXT".to_string())));
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[1].body.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "TXT",
+                "name": "a.example.com",
+                "content": "\"ip=203.0.113.5\"",
+                "ttl": 300,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn cname_target_is_compared_ignoring_case() {
+        let config = "base_url: https://cf.test\nzone_id: z\napi_token: t\nrecord_type: CNAME\ncname_target: home.dyn.example.net";
+        let listed = |target: &str| {
+            format!(
+                r#"{{"success": true, "errors": [], "result": [{{"id": "rec1", "name": "a.example.com", "type": "CNAME", "content": "{}", "ttl": 300, "proxied": false}}]}}"#,
+                target
+            )
+        };
+        let (cloudflare, client) = mock_cloudflare_with(
+            config,
+            MockHttpClient::default()
+                .respond(200, &listed("Home.Dyn.Example.NET."))
+                .respond(200, &listed("old.dyn.example.net"))
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+        let update = || {
+            cloudflare.update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+        };
+
+        assert!(!update().await.unwrap().written);
+        assert!(update().await.unwrap().written);
+
+        let requests = client.requests();
+        assert!(requests[0]
+            .query
+            .contains(&("type".to_string(), "CNAME".to_string())));
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[2].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body["content"], "home.dyn.example.net");
+        assert_eq!(body["type"], "CNAME");
+
+        let mut config: CloudflareConfig =
+            serde_yaml::from_str("zone_id: z\napi_token: t\nrecord_type: CNAME").unwrap();
+        assert!(config.resolve_env().is_err());
+    }
+
+    #[tokio::test]
+    async fn proxied_setting_doesnt_rewrite_a_txt_record() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nrecord_type: TXT\nproxied: true",
+            MockHttpClient::default().respond(
+                200,
+                r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "\"203.0.113.5\"", "ttl": 300, "proxied": false}]}"#,
+            ),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(!outcome.written);
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    const LIST_RES_B: &str = r#"{"success": true, "errors": [], "result": [{"id": "rec2", "name": "b.example.com", "content": "198.51.100.1", "ttl": 300, "proxied": false}]}"#;
+
+    fn two_names() -> Vec<String> {
+        vec!["a.example.com".to_string(), "b.example.com".to_string()]
+    }
+
+    #[tokio::test]
+    async fn several_names_are_patched_in_one_batch() {
+        let (cloudflare, client) = mock_cloudflare(
+            MockHttpClient::default()
+                .respond(200, LIST_RES)
+                .respond(200, LIST_RES_B)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcomes = cloudflare
+            .update_many(&two_names(), "203.0.113.5".parse().unwrap(), false)
+            .await;
+
+        assert!(outcomes
+            .iter()
+            .all(|outcome| outcome.as_ref().unwrap().written));
+        let requests = client.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].method, reqwest::Method::POST);
+        assert_eq!(requests[2].url, "https://cf.test/zones/z/dns_records/batch");
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[2].body.as_ref().unwrap()).unwrap();
+        let ids: Vec<&str> = body["patches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|patch| patch["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["rec1", "rec2"]);
+    }
+
+    #[tokio::test]
+    async fn failed_batch_falls_back_to_one_at_a_time() {
+        let (cloudflare, client) = mock_cloudflare(
+            MockHttpClient::default()
+                .respond(200, LIST_RES)
+                .respond(200, LIST_RES_B)
+                .respond(
+                    404,
+                    r#"{"success": false, "errors": [{"code": 7003, "message": "No route"}], "result": null}"#,
+                )
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcomes = cloudflare
+            .update_many(&two_names(), "203.0.113.5".parse().unwrap(), false)
+            .await;
+
+        assert!(outcomes
+            .iter()
+            .all(|outcome| outcome.as_ref().unwrap().written));
+        let requests = client.requests();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(requests[3].url, "https://cf.test/zones/z/dns_records/rec1");
+        assert_eq!(requests[4].url, "https://cf.test/zones/z/dns_records/rec2");
+    }
+
+    #[tokio::test]
+    async fn names_sharing_a_record_write_it_once() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nrecord_name: a.example.com\nretry:\n  max_retries: 0",
+            MockHttpClient::default()
+                .respond(200, LIST_RES)
+                .respond(200, LIST_RES)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcomes = cloudflare
+            .update_many(&two_names(), "203.0.113.5".parse().unwrap(), false)
+            .await;
+
+        assert!(outcomes
+            .iter()
+            .all(|outcome| outcome.as_ref().unwrap().written));
+        let requests = client.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].url, "https://cf.test/zones/z/dns_records/rec1");
+    }
+
+    #[tokio::test]
+    async fn record_snapshot_is_shared_and_kept_up_to_date() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nrecord_cache_secs: 300\nretry:\n  max_retries: 0",
+            MockHttpClient::default()
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result_info": {"total_pages": 1}, "result": [
+                        {"id": "rec1", "name": "a.example.com", "type": "A", "content": "198.51.100.1", "ttl": 300},
+                        {"id": "rec2", "name": "b.example.com", "type": "A", "content": "198.51.100.1", "ttl": 300},
+                        {"id": "rec3", "name": "b.example.com", "type": "AAAA", "content": "2001:db8::1", "ttl": 300}
+                    ]}"#,
+                )
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+        let ip = "203.0.113.5".parse().unwrap();
+
+        let first = cloudflare.update_many(&two_names(), ip, false).await;
+        let second = cloudflare.update_many(&two_names(), ip, false).await;
+
+        assert!(first
+            .iter()
+            .all(|outcome| outcome.as_ref().unwrap().written));
+        assert!(second
+            .iter()
+            .all(|outcome| *outcome.as_ref().unwrap() == UpdateOutcome::unchanged(ip)));
+        let requests = client.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].url, "https://cf.test/zones/z/dns_records/batch");
+    }
+
+    #[tokio::test]
+    async fn conflicting_cname_is_replaced() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nreplace_conflicting: true\nretry:\n  max_retries: 0",
+            MockHttpClient::default()
+                .respond(200, r#"{"success": true, "errors": [], "result": []}"#)
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": [{"id": "cname1", "name": "a.example.com", "type": "CNAME", "content": "elsewhere.example.net", "ttl": 1}]}"#,
+                )
+                .respond(200, r#"{"success": true, "errors": [], "result": {"id": "cname1"}}"#)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(outcome.written);
+        let requests = client.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[1]
+            .query
+            .contains(&("type".to_string(), "CNAME".to_string())));
+        assert_eq!(requests[2].method, reqwest::Method::DELETE);
+        assert_eq!(
+            requests[2].url,
+            "https://cf.test/zones/z/dns_records/cname1"
+        );
+        assert_eq!(requests[3].method, reqwest::Method::POST);
+    }
+
+    #[tokio::test]
+    async fn other_records_make_way_for_a_cname() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\napi_token: t\nreplace_conflicting: true\nrecord_type: CNAME\ncname_target: home.dyn.example.net\nretry:\n  max_retries: 0",
+            MockHttpClient::default()
+                .respond(200, r#"{"success": true, "errors": [], "result": []}"#)
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": [{"id": "a1", "name": "a.example.com", "type": "A", "content": "198.51.100.1", "ttl": 300}, {"id": "txt1", "name": "a.example.com", "type": "TXT", "content": "\"note\"", "ttl": 300}]}"#,
+                )
+                .respond(200, r#"{"success": true, "errors": [], "result": {"id": "a1"}}"#)
+                .respond(200, r#"{"success": true, "errors": [], "result": {"id": "txt1"}}"#)
+                .respond(200, r#"{"success": true, "errors": [], "result": {}}"#),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(outcome.written);
+        let requests = client.requests();
+        assert_eq!(requests.len(), 5);
+        assert!(!requests[1].query.iter().any(|(key, _)| key == "type"));
+        assert_eq!(requests[2].method, reqwest::Method::DELETE);
+        assert_eq!(requests[2].url, "https://cf.test/zones/z/dns_records/a1");
+        assert_eq!(requests[3].url, "https://cf.test/zones/z/dns_records/txt1");
+        assert_eq!(requests[4].method, reqwest::Method::POST);
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[4].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body["type"], "CNAME");
+    }
+
+    #[test]
+    fn content_is_compared_the_way_dns_reads_it() {
+        assert!(content_matches("A", "203.0.113.5", "203.0.113.5"));
+        assert!(!content_matches("A", "203.0.113.5", "203.0.113.6"));
+        assert!(content_matches("AAAA", "2001:DB8:0::1", "2001:db8::1"));
+        assert!(content_matches(
+            "CNAME",
+            "Home.Example.COM.",
+            "home.example.com"
+        ));
+        assert!(content_matches(
+            "CNAME",
+            "home.example.com",
+            "HOME.example.com."
+        ));
+        assert!(!content_matches(
+            "CNAME",
+            "home.example.com",
+            "home.example.org"
+        ));
+        assert!(content_matches(
+            "TXT",
+            "home=203.0.113.5",
+            "\"home=203.0.113.5\""
+        ));
+        assert!(!content_matches(
+            "TXT",
+            "\"Home=203.0.113.5\"",
+            "\"home=203.0.113.5\""
+        ));
+    }
+
+    #[test]
+    fn names_are_normalized_against_the_zone() {
+        let (cloudflare, _) = mock_cloudflare_with(
+            "zone_name: example.com\napi_token: t",
+            MockHttpClient::default(),
+        );
+
+        for (name, expected) in [
+            ("home", "home.example.com"),
+            ("home.example.com", "home.example.com"),
+            ("home.example.com.", "home.example.com"),
+            ("Home.Example.COM", "Home.Example.COM"),
+            ("example.com", "example.com"),
+            ("EXAMPLE.com", "example.com"),
+            ("@", "example.com"),
+            ("home.lan", "home.lan.example.com"),
+            ("home.example.org.", "home.example.org"),
+        ] {
+            assert_eq!(cloudflare.normalize_name(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn names_only_lose_the_trailing_dot_without_a_zone_name() {
+        let (cloudflare, _) = mock_cloudflare(MockHttpClient::default());
+
+        assert_eq!(cloudflare.normalize_name("home"), "home");
+        assert_eq!(
+            cloudflare.normalize_name("home.example.com."),
+            "home.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn forbidden_is_an_error() {
+        let (cloudflare, client) = mock_cloudflare(MockHttpClient::default().respond(
+            403,
+            r#"{"success": false, "errors": [{"code": 9109, "message": "Invalid access token"}], "result": null}"#,
+        ));
+
+        let error = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(
+            format!("{:#}", error).contains("[9109] Invalid access token"),
+            "{:#}",
+            error
+        );
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    const VERIFY_CONFIG: &str = "base_url: https://cf.test\nzone_id: z\napi_token: t\nverify_token: true\nretry:\n  max_retries: 0";
+    const ACTIVE_TOKEN_RES: &str =
+        r#"{"success": true, "errors": [], "result": {"id": "tok", "status": "active"}}"#;
+
+    #[tokio::test]
+    async fn token_is_verified_before_the_first_update_only() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            VERIFY_CONFIG,
+            MockHttpClient::default()
+                .respond(200, ACTIVE_TOKEN_RES)
+                .respond(
+                    200,
+                    r##"{"success": true, "errors": [], "result": {"id": "z", "name": "example.com", "permissions": ["#zone:read", "#dns_records:edit"]}}"##,
+                )
+                .respond(200, LIST_RES)
+                .respond(200, LIST_RES),
+        );
+
+        for _ in 0..2 {
+            cloudflare
+                .update_dns(
+                    "a.example.com".to_string(),
+                    "198.51.100.1".parse().unwrap(),
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].url, "https://cf.test/user/tokens/verify");
+        assert_eq!(requests[1].url, "https://cf.test/zones/z");
+    }
+
+    #[tokio::test]
+    async fn token_without_dns_edit_fails_before_updating() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            VERIFY_CONFIG,
+            MockHttpClient::default()
+                .respond(200, ACTIVE_TOKEN_RES)
+                .respond(
+                    200,
+                    r##"{"success": true, "errors": [], "result": {"id": "z", "name": "example.com", "permissions": ["#zone:read", "#dns_records:read"]}}"##,
+                ),
+        );
+
+        let error = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", error),
+            "Cloudflare token valid but missing DNS:Edit on zone example.com"
+        );
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn name_outside_the_zone_fails_before_listing() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            VERIFY_CONFIG,
+            MockHttpClient::default()
+                .respond(200, ACTIVE_TOKEN_RES)
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": {"id": "z", "name": "example.com"}}"#,
+                ),
+        );
+
+        let error = cloudflare
+            .update_dns(
+                "home.example.org".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Name home.example.org is not in zone example.com"
+        );
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn zone_name_is_looked_up_once() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_name: example.com\napi_token: t\nretry:\n  max_retries: 0",
+            MockHttpClient::default()
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": [{"id": "z", "name": "example.com"}]}"#,
+                )
+                .respond(200, LIST_RES)
+                .respond(200, LIST_RES),
+        );
+
+        for _ in 0..2 {
+            cloudflare
+                .update_dns(
+                    "a.example.com".to_string(),
+                    "198.51.100.1".parse().unwrap(),
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].url, "https://cf.test/zones");
+        assert_eq!(
+            requests[0].query,
+            [("name".to_string(), "example.com".to_string())]
+        );
+        assert_eq!(requests[1].url, "https://cf.test/zones/z/dns_records");
+        assert_eq!(requests[2].url, "https://cf.test/zones/z/dns_records");
+    }
+
+    const RETRYING_CONFIG: &str =
+        "base_url: https://cf.test\nzone_id: z\napi_token: t\nretry:\n  max_retries: 1";
+
+    #[tokio::test]
+    async fn rate_limit_is_waited_out() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            RETRYING_CONFIG,
+            MockHttpClient::default()
+                .respond_rate_limited(0)
+                .respond(200, LIST_RES),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "198.51.100.1".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert!(!outcome.written);
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn lasting_rate_limit_is_an_error() {
+        let (cloudflare, _) = mock_cloudflare_with(
+            RETRYING_CONFIG,
+            MockHttpClient::default()
+                .respond_rate_limited(0)
+                .respond_rate_limited(0),
+        );
+
+        let error = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "198.51.100.1".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(
+            format!("{:#}", error).contains("Rate limited, retried after 0 seconds"),
+            "{:#}",
+            error
+        );
+        assert!(
+            matches!(error, UpdateDnsError::RateLimited(_)),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn failures_keep_their_kind() {
+        let update = |client| async {
+            let (cloudflare, _) = mock_cloudflare(client);
+            cloudflare
+                .update_dns(
+                    "a.example.com".to_string(),
+                    "198.51.100.1".parse().unwrap(),
+                    false,
+                )
+                .await
+                .unwrap_err()
+        };
+
+        let error = update(MockHttpClient::default().respond(
+            403,
+            r#"{"success": false, "errors": [{"code": 9109, "message": "Invalid access token"}], "result": null}"#,
+        ))
+        .await;
+        assert!(matches!(error, UpdateDnsError::Auth(_)), "{:?}", error);
+        assert!(format!("{:#}", error)
+            .contains("403 Forbidden Error from Cloudflare: [9109] Invalid access token"));
+
+        let error = update(MockHttpClient::default().respond(200, "<html>")).await;
+        assert!(matches!(error, UpdateDnsError::Parse(_)), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn check_only_reads_the_zone() {
+        let (cloudflare, client) = mock_cloudflare(MockHttpClient::default().respond(
+            200,
+            r#"{"success": true, "errors": [], "result": {"id": "z", "name": "example.com"}}"#,
+        ));
+
+        cloudflare.check().await.unwrap().unwrap();
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, reqwest::Method::GET);
+        assert_eq!(requests[0].url, "https://cf.test/zones/z");
+    }
+
+    #[test]
+    fn unsuccessful_response_is_an_error() {
+        let cf_res: CloudflareResponse<Vec<CloudflareListDnsRecordRes>> = serde_json::from_str(
+            r#"{"success": false, "errors": [{"code": 1003, "message": "Invalid zone"}], "result": null}"#,
+        )
+        .unwrap();
+
+        let error = cf_res.into_result().unwrap_err();
+
+        assert!(
+            error.to_string().contains("[1003] Invalid zone"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn successful_response_without_result_is_an_error() {
+        let cf_res: CloudflareResponse<Vec<CloudflareListDnsRecordRes>> =
+            serde_json::from_str(r#"{"success": true, "errors": [], "result": null}"#).unwrap();
+
+        assert!(cf_res.into_result().is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_go_through_configured_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        // Answers a single request as if it were Cloudflare, returning its request line
+        let stub = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+            let body = r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "203.0.113.5", "ttl": 1}]}"#;
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            request_line
+        });
+        let config: CloudflareConfig = serde_yaml::from_str(&format!(
+            "base_url: http://cloudflare.invalid/client/v4\nzone_id: z\napi_token: t\nproxy: {}",
+            proxy
+        ))
+        .unwrap();
+
+        Cloudflare::from_config(config)
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                true,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stub.join().unwrap().trim_end(),
+            "GET http://cloudflare.invalid/client/v4/zones/z/dns_records?name=a.example.com&type=A HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn names_only_go_to_their_own_zone() {
+        let client = std::sync::Arc::new(
+            MockHttpClient::default()
+                .respond(200, LIST_RES)
+                .respond(200, LIST_RES),
+        );
+        let config: CloudflareConfig = serde_yaml::from_str(
+            "base_url: https://cf.test\napi_token: t\nretry:\n  max_retries: 0\nzones:\n  - zone_id: z1\n    names: [a.example.com]\n  - zone_id: z2\n    names: [b.example.org]",
+        )
+        .unwrap();
+        let zones = CloudflareZones::with_clients(config, |_| Box::new(client.clone()));
+
+        let names = [
+            "b.example.org".to_string(),
+            "c.example.net".to_string(),
+            "a.example.com".to_string(),
+        ];
+        let results = zones
+            .update_many(&names, "198.51.100.1".parse().unwrap(), false)
+            .await;
+
+        assert!(results[0].is_ok());
+        assert_eq!(
+            format!("{:#}", results[1].as_ref().unwrap_err()),
+            "c.example.net isn't listed in any of the Cloudflare zones"
+        );
+        assert!(results[2].is_ok());
+        let requests = client.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url, "https://cf.test/zones/z1/dns_records");
+        assert_eq!(
+            requests[0].query,
+            [
+                ("name".to_string(), "a.example.com".to_string()),
+                ("type".to_string(), "A".to_string())
+            ]
+        );
+        assert_eq!(requests[1].url, "https://cf.test/zones/z2/dns_records");
+    }
+
+    #[tokio::test]
+    async fn record_name_is_looked_up_instead_of_the_dns_name() {
+        let (cloudflare, client) = mock_cloudflare_with(
+            "base_url: https://cf.test\nzone_id: z\nzone_name: example.com\nrecord_name: a\napi_token: t",
+            MockHttpClient::default().respond(200, LIST_RES),
+        );
+
+        cloudflare
+            .update_dns("home".to_string(), "198.51.100.1".parse().unwrap(), false)
+            .await
+            .unwrap();
+
+        assert!(client.requests()[0]
+            .query
+            .contains(&("name".to_string(), "a.example.com".to_string())));
+    }
+
+    #[tokio::test]
+    async fn modification_times_are_read_back() {
+        let (cloudflare, _) = mock_cloudflare(
+            MockHttpClient::default()
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": [{"id": "rec1", "name": "a.example.com", "content": "198.51.100.1", "ttl": 300, "modified_on": "2024-03-01T10:00:00.123456Z"}]}"#,
+                )
+                .respond(
+                    200,
+                    r#"{"success": true, "errors": [], "result": {"id": "rec1", "modified_on": "2024-03-02T08:30:00.5Z"}}"#,
+                ),
+        );
+
+        let outcome = cloudflare
+            .update_dns(
+                "a.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            outcome.modified_on,
+            Some(ModifiedOn {
+                found: DateTime::parse_from_rfc3339("2024-03-01T10:00:00.123456Z").ok(),
+                now: DateTime::parse_from_rfc3339("2024-03-02T08:30:00.5Z").ok(),
+            })
+        );
+    }
 }
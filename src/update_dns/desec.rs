@@ -0,0 +1,181 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+pub struct Desec {
+    config: DesecConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DesecConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub token: Secret<String>,
+    pub domain: String,
+    /// TTL to set on the rrset, deSEC doesn't accept less than 3600 on most accounts.
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://desec.io/api/v1".to_string()
+}
+
+fn default_ttl() -> u32 {
+    3600
+}
+
+impl Desec {
+    async fn create_desec_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from deSEC: {de}",
+            status = status,
+            de = response
+                .text()
+                .await
+                .unwrap_or_else(|e| format!("Unable to read response: {:?}", e)),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    /// deSEC wants the name relative to the domain, empty for the domain itself.
+    fn subname(&self, name: &str) -> color_eyre::Result<String> {
+        relative_name(name, &self.config.domain, "")
+            .ok_or_else(|| eyre!("{} is not in domain {}", name, self.config.domain))
+    }
+}
+
+impl UpdateDnsCreator for Desec {
+    type Config = DesecConfig;
+    const TAG: &'static str = "desec";
+    const DESCRIPTION: &'static str = "deSEC, by domain";
+
+    fn from_config(config: Self::Config) -> Self {
+        Desec {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Desec {
+    fn describe(&self) -> String {
+        format!("Desec[domain={domain}]", domain = &self.config.domain)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let subname = self.subname(&name)?;
+
+        // GET the rrset, the domain itself is addressed as `@` in the URL
+        let response = self
+            .client
+            .get(format!(
+                "{base}/domains/{domain}/rrsets/{subname}/{record_type}/",
+                base = self.config.base_url,
+                domain = &self.config.domain,
+                subname = if subname.is_empty() { "@" } else { &subname },
+                record_type = record_type,
+            ))
+            .header("Authorization", format!("Token {}", *self.config.token))
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        let existing = match response.status() {
+            StatusCode::NOT_FOUND => None,
+            status if status.is_success() => Some(
+                response
+                    .json::<DesecRrset>()
+                    .await
+                    .wrap_err("Failed to read response")?,
+            ),
+            _ => return Err(Desec::create_desec_error(response).await),
+        };
+
+        match &existing {
+            Some(rrset)
+                if rrset.records == [new_ip.to_string()]
+                    && rrset.ttl == self.config.ttl.unwrap_or_else(default_ttl) =>
+            {
+                info!(
+                    "[desec] New IP is the same as existing {} record, skipping update.",
+                    record_type
+                );
+                return Ok(UpdateOutcome::unchanged(new_ip));
+            }
+            Some(rrset) => info!(
+                "[desec] Old {} content was {} with TTL {}",
+                record_type,
+                rrset.records.join(", "),
+                rrset.ttl
+            ),
+            None => info!("[desec] No existing {} record, creating one.", record_type),
+        }
+
+        let old_ip = existing
+            .as_ref()
+            .and_then(|rrset| rrset.records.first())
+            .and_then(|ip| ip.parse().ok());
+        if dry_run {
+            info!(
+                "[desec] Dry run, would set {} {} record to {}",
+                name, record_type, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        // A bulk PATCH creates the rrset or replaces its records, whichever is needed
+        let response = self
+            .client
+            .patch(format!(
+                "{base}/domains/{domain}/rrsets/",
+                base = self.config.base_url,
+                domain = &self.config.domain,
+            ))
+            .header("Authorization", format!("Token {}", *self.config.token))
+            .json(&[DesecRrset {
+                subname,
+                record_type: record_type.to_string(),
+                ttl: self.config.ttl.unwrap_or_else(default_ttl),
+                records: vec![new_ip.to_string()],
+            }])
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Desec::create_desec_error(response).await);
+        }
+
+        let rrsets: Vec<DesecRrset> = response.json().await.wrap_err("Failed to read response")?;
+        info!("Successful: {:?}", rrsets);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DesecRrset {
+    subname: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    ttl: u32,
+    records: Vec<String>,
+}
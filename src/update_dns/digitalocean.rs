@@ -0,0 +1,179 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+pub struct DigitalOcean {
+    config: DigitalOceanConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DigitalOceanConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub api_token: Secret<String>,
+    pub domain: String,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://api.digitalocean.com/v2".to_string()
+}
+
+impl DigitalOcean {
+    async fn create_do_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from DigitalOcean: {de}",
+            status = status,
+            de = response.json::<DigitalOceanError>().await.map_or_else(
+                |e| format!("Unable to read response: {:?}", e),
+                |v| format!("[{}] {}", v.id, v.message),
+            ),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+}
+
+impl UpdateDnsCreator for DigitalOcean {
+    type Config = DigitalOceanConfig;
+    const TAG: &'static str = "digitalocean";
+    const DESCRIPTION: &'static str = "DigitalOcean DNS, by domain";
+
+    fn from_config(config: Self::Config) -> Self {
+        DigitalOcean {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for DigitalOcean {
+    fn describe(&self) -> String {
+        format!(
+            "DigitalOcean[domain={domain}]",
+            domain = &self.config.domain
+        )
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+
+        // GET all `name` records of the address's type, DigitalOcean wants the full name here
+        let response = self
+            .client
+            .get(format!(
+                "{base}/domains/{domain}/records",
+                base = self.config.base_url,
+                domain = &self.config.domain,
+            ))
+            .query(&[("name", name.as_str()), ("type", record_type)])
+            .bearer_auth(self.config.api_token.as_str())
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(DigitalOcean::create_do_error(response).await);
+        }
+
+        let do_res: DigitalOceanListRecordsRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        let list = do_res.domain_records;
+        let record = match list.as_slice() {
+            [r] => r,
+            _ => return Err(eyre!("Expected exactly one result, got {:?}", list).into()),
+        };
+
+        if record.data == new_ip.to_string() {
+            info!(
+                "[digitalocean] New IP is the same as existing {} record, skipping update.",
+                record_type
+            );
+            return Ok(UpdateOutcome::unchanged(new_ip));
+        }
+
+        let old_ip = record.data.parse().ok();
+        info!(
+            "[digitalocean] Old {} content was {}",
+            record_type, record.data
+        );
+
+        if dry_run {
+            info!(
+                "[digitalocean] Dry run, would update {} from {} to {}",
+                name, record.data, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let response = self
+            .client
+            .patch(format!(
+                "{base}/domains/{domain}/records/{id}",
+                base = self.config.base_url,
+                domain = &self.config.domain,
+                id = record.id,
+            ))
+            .json(&DigitalOceanUpdateRecordReq {
+                record_type: record_type.to_string(),
+                data: new_ip.to_string(),
+            })
+            .bearer_auth(self.config.api_token.as_str())
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(DigitalOcean::create_do_error(response).await);
+        }
+
+        let do_res: DigitalOceanUpdateRecordRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        info!("Successful: {:?}", do_res.domain_record);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct DigitalOceanError {
+    id: String,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct DigitalOceanListRecordsRes {
+    domain_records: Vec<DigitalOceanRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DigitalOceanUpdateRecordRes {
+    domain_record: DigitalOceanRecord,
+}
+
+#[derive(Deserialize, Debug)]
+struct DigitalOceanRecord {
+    id: u64,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct DigitalOceanUpdateRecordReq {
+    #[serde(rename = "type")]
+    record_type: String,
+    data: String,
+}
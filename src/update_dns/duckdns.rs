@@ -0,0 +1,136 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+const DUCKDNS_SUFFIX: &str = ".duckdns.org";
+
+/// DuckDNS updates the subdomains listed in `domains` when given, otherwise the one taken from the
+/// name being updated.
+pub struct DuckDns {
+    config: DuckDnsConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DuckDnsConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub token: Secret<String>,
+    /// Comma-separated subdomains, with or without `.duckdns.org`.
+    pub domains: Option<String>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://www.duckdns.org".to_string()
+}
+
+impl DuckDns {
+    fn domains(&self, name: &str) -> String {
+        let domains = self.config.domains.as_deref().unwrap_or(name);
+        domains
+            .split(',')
+            .map(|domain| {
+                let domain = domain.trim().trim_end_matches('.');
+                domain.strip_suffix(DUCKDNS_SUFFIX).unwrap_or(domain)
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl UpdateDnsCreator for DuckDns {
+    type Config = DuckDnsConfig;
+    const TAG: &'static str = "duckdns";
+    const DESCRIPTION: &'static str = "DuckDNS subdomains";
+
+    fn from_config(config: Self::Config) -> Self {
+        DuckDns {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for DuckDns {
+    fn describe(&self) -> String {
+        match &self.config.domains {
+            Some(domains) => format!("DuckDns[domains={domains}]", domains = domains),
+            None => "DuckDns".to_string(),
+        }
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let domains = self.domains(&name);
+        let ip_param = match new_ip {
+            IpAddr::V4(_) => "ip",
+            IpAddr::V6(_) => "ipv6",
+        };
+
+        // There's no way to read the current value, but a verbose update says if it changed
+        if dry_run {
+            info!("[duckdns] Dry run, would update {} to {}", domains, new_ip);
+            return Ok(UpdateOutcome::not_written(None, new_ip));
+        }
+
+        let response = self
+            .client
+            .get(format!("{base}/update", base = self.config.base_url))
+            .query(&[
+                ("domains", domains.as_str()),
+                ("token", self.config.token.as_str()),
+                (ip_param, &new_ip.to_string()),
+                ("verbose", "true"),
+            ])
+            .send()
+            .await
+            // The URL has the token in it
+            .map_err(reqwest::Error::without_url)
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(UpdateDnsError::from_status(
+                response.status(),
+                eyre!("{} Error from DuckDNS", response.status()),
+            ));
+        }
+
+        let body = response
+            .text()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .wrap_err("Failed to read response")?;
+        let mut lines = body.lines();
+        if lines.next() != Some("OK") {
+            // DuckDNS only says KO, which is almost always the token or a domain it doesn't own
+            return Err(UpdateDnsError::Auth(eyre!(
+                "Error from DuckDNS for {}, check the token and domains",
+                domains
+            )));
+        }
+        match lines.last() {
+            Some("NOCHANGE") => {
+                info!(
+                    "[duckdns] New IP is the same as existing for {}, nothing changed.",
+                    domains
+                );
+                Ok(UpdateOutcome::unchanged(new_ip))
+            }
+            _ => {
+                info!("Successful: {} is now {}", domains, new_ip);
+                Ok(UpdateOutcome::written(None, new_ip))
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, Report, WrapErr};
+use log::info;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// Any service speaking the DynDNS2 protocol, as No-IP, Dyn, FreeDNS and many others do,
+/// updating the configured `hostname` when given, otherwise the name being updated.
+pub struct DynDns2 {
+    config: DynDns2Config,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DynDns2Config {
+    /// Where the service's `/nic/update` lives, e.g. `https://dynupdate.no-ip.com`.
+    pub base_url: String,
+    pub username: String,
+    pub password: Secret<String>,
+    pub hostname: Option<String>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+impl UpdateDnsCreator for DynDns2 {
+    type Config = DynDns2Config;
+    const TAG: &'static str = "dyndns2";
+    const DESCRIPTION: &'static str = "Any DynDNS2 service, e.g. No-IP or FreeDNS, by hostname";
+
+    fn from_config(config: Self::Config) -> Self {
+        DynDns2 {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for DynDns2 {
+    fn describe(&self) -> String {
+        format!("DynDns2[{base}]", base = self.config.base_url)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let hostname = self
+            .config
+            .hostname
+            .as_deref()
+            .unwrap_or(&name)
+            .trim_end_matches('.');
+
+        // There's no way to read the current value, but the response says if it changed
+        if dry_run {
+            info!("[dyndns2] Dry run, would update {} to {}", hostname, new_ip);
+            return Ok(UpdateOutcome::not_written(None, new_ip));
+        }
+
+        let response = self
+            .client
+            .get(format!("{base}/nic/update", base = self.config.base_url))
+            .basic_auth(&self.config.username, Some(self.config.password.as_str()))
+            .query(&[("hostname", hostname), ("myip", &new_ip.to_string())])
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(UpdateDnsError::from_status(
+                response.status(),
+                eyre!("{} Error from {}", response.status(), self.config.base_url),
+            ));
+        }
+
+        let body = response.text().await.wrap_err("Failed to read response")?;
+        read_response(&body, hostname, new_ip, Self::TAG, &self.config.base_url)
+    }
+}
+
+/// The first word of the body is the result code, the same for every DynDNS2 service. `tag` is
+/// the provider's, for logs, and `service` names it in errors.
+pub(crate) fn read_response(
+    body: &str,
+    hostname: &str,
+    new_ip: IpAddr,
+    tag: &str,
+    service: &str,
+) -> UpdateResult {
+    let code = body.split_whitespace().next().unwrap_or_default();
+    let (problem, kind): (_, fn(Report) -> UpdateDnsError) = match code {
+        "good" => {
+            info!("Successful: {} is now {}", hostname, new_ip);
+            return Ok(UpdateOutcome::written(None, new_ip));
+        }
+        "nochg" => {
+            info!(
+                "[{}] New IP is the same as existing for {}, nothing changed.",
+                tag, hostname
+            );
+            return Ok(UpdateOutcome::unchanged(new_ip));
+        }
+        "badauth" => ("rejected the username and password", UpdateDnsError::Auth),
+        "nohost" => (
+            "has no such hostname in the account",
+            UpdateDnsError::NotFound,
+        ),
+        "notfqdn" => ("needs a full hostname", UpdateDnsError::NotFound),
+        "numhost" => ("was given too many hostnames", UpdateDnsError::Other),
+        "badagent" => ("blocked this client's requests", UpdateDnsError::Auth),
+        "!donator" => ("only offers this to paying accounts", UpdateDnsError::Auth),
+        "abuse" => (
+            "blocked the hostname for abuse",
+            UpdateDnsError::RateLimited,
+        ),
+        "dnserr" | "911" => (
+            "has a problem on its end, try again later",
+            UpdateDnsError::Http,
+        ),
+        _ => {
+            return Err(UpdateDnsError::Parse(eyre!(
+                "Error from {} for {}: {}",
+                service,
+                hostname,
+                body.trim()
+            )))
+        }
+    };
+    Err(kind(eyre!(
+        "{} {} for {} ({})",
+        service,
+        problem,
+        hostname,
+        code
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_codes_become_outcomes() {
+        let ip: IpAddr = "203.0.113.5".parse().unwrap();
+        let read = |body| read_response(body, "home.example.com", ip, "dyndns2", "No-IP");
+
+        assert!(read("good 203.0.113.5").unwrap().written);
+        assert_eq!(read("nochg\n").unwrap(), UpdateOutcome::unchanged(ip));
+        let error = read("nohost").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No-IP has no such hostname in the account for home.example.com (nohost)"
+        );
+        assert!(matches!(error, UpdateDnsError::NotFound(_)), "{:?}", error);
+        assert!(matches!(
+            read("badauth").unwrap_err(),
+            UpdateDnsError::Auth(_)
+        ));
+        assert!(matches!(
+            read("abuse").unwrap_err(),
+            UpdateDnsError::RateLimited(_)
+        ));
+        assert!(matches!(read("911").unwrap_err(), UpdateDnsError::Http(_)));
+        let error = read("<html>").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Error from No-IP for home.example.com: <html>"
+        );
+        assert!(matches!(error, UpdateDnsError::Parse(_)), "{:?}", error);
+    }
+}
@@ -0,0 +1,97 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+use crate::update_dns::dyndns2::read_response;
+
+/// Dynu's DynDNS2-style `nic/update` endpoint, which updates the configured `hostname` when given, otherwise the
+/// name being updated.
+pub struct Dynu {
+    config: DynuConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DynuConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub username: String,
+    /// The account password, or its MD5 or SHA-256 hash as Dynu also accepts.
+    pub password: Secret<String>,
+    pub hostname: Option<String>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://api.dynu.com".to_string()
+}
+
+impl UpdateDnsCreator for Dynu {
+    type Config = DynuConfig;
+    const TAG: &'static str = "dynu";
+    const DESCRIPTION: &'static str = "Dynu's Dynamic DNS, by hostname";
+
+    fn from_config(config: Self::Config) -> Self {
+        Dynu {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Dynu {
+    fn describe(&self) -> String {
+        match &self.config.hostname {
+            Some(hostname) => format!("Dynu[hostname={hostname}]", hostname = hostname),
+            None => "Dynu".to_string(),
+        }
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let hostname = self
+            .config
+            .hostname
+            .as_deref()
+            .unwrap_or(&name)
+            .trim_end_matches('.');
+        let ip_param = match new_ip {
+            IpAddr::V4(_) => "myip",
+            IpAddr::V6(_) => "myipv6",
+        };
+
+        // There's no way to read the current value, but the response says if it changed
+        if dry_run {
+            info!("[dynu] Dry run, would update {} to {}", hostname, new_ip);
+            return Ok(UpdateOutcome::not_written(None, new_ip));
+        }
+
+        let response = self
+            .client
+            .get(format!("{base}/nic/update", base = self.config.base_url))
+            .basic_auth(&self.config.username, Some(self.config.password.as_str()))
+            .query(&[("hostname", hostname), (ip_param, &new_ip.to_string())])
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(UpdateDnsError::from_status(
+                response.status(),
+                eyre!("{} Error from Dynu", response.status()),
+            ));
+        }
+
+        let body = response.text().await.wrap_err("Failed to read response")?;
+        read_response(&body, hostname, new_ip, Self::TAG, "Dynu")
+    }
+}
@@ -0,0 +1,182 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+pub struct Gandi {
+    config: GandiConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GandiConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub api_key: Secret<String>,
+    /// The domain the records are in.
+    pub fqdn: String,
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://api.gandi.net/v5/livedns".to_string()
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+impl Gandi {
+    async fn create_gandi_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from Gandi: {de}",
+            status = status,
+            de = response.json::<GandiError>().await.map_or_else(
+                |e| format!("Unable to read response: {:?}", e),
+                |v| v.message,
+            ),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    /// LiveDNS wants the name relative to the domain, `@` for the domain itself.
+    fn record_name(&self, name: &str) -> color_eyre::Result<String> {
+        relative_name(name, &self.config.fqdn, "@")
+            .ok_or_else(|| eyre!("{} is not in domain {}", name, self.config.fqdn))
+    }
+}
+
+impl UpdateDnsCreator for Gandi {
+    type Config = GandiConfig;
+    const TAG: &'static str = "gandi";
+    const DESCRIPTION: &'static str = "Gandi LiveDNS, by domain";
+
+    fn from_config(config: Self::Config) -> Self {
+        Gandi {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Gandi {
+    fn describe(&self) -> String {
+        format!("Gandi[fqdn={fqdn}]", fqdn = &self.config.fqdn)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let url = format!(
+            "{base}/domains/{fqdn}/records/{name}/{record_type}",
+            base = self.config.base_url,
+            fqdn = &self.config.fqdn,
+            name = self.record_name(&name)?,
+            record_type = record_type,
+        );
+
+        // GET the current rrset, the PUT replaces it wholesale so this is only to skip no-ops
+        let response = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("Apikey {}", *self.config.api_key))
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        let existing = match response.status() {
+            StatusCode::NOT_FOUND => None,
+            status if status.is_success() => Some(
+                response
+                    .json::<GandiRrset>()
+                    .await
+                    .wrap_err("Failed to read response")?,
+            ),
+            _ => return Err(Gandi::create_gandi_error(response).await),
+        };
+
+        match &existing {
+            Some(rrset)
+                if rrset.rrset_values == [new_ip.to_string()]
+                    && rrset.rrset_ttl == self.config.ttl.unwrap_or_else(default_ttl) =>
+            {
+                info!(
+                    "[gandi] New IP is the same as existing {} record, skipping update.",
+                    record_type
+                );
+                return Ok(UpdateOutcome::unchanged(new_ip));
+            }
+            Some(rrset) => info!(
+                "[gandi] Old {} content was {} with TTL {}",
+                record_type,
+                rrset.rrset_values.join(", "),
+                rrset.rrset_ttl
+            ),
+            None => info!("[gandi] No existing {} record, creating one.", record_type),
+        }
+
+        let old_ip = existing
+            .as_ref()
+            .and_then(|rrset| rrset.rrset_values.first())
+            .and_then(|ip| ip.parse().ok());
+        if dry_run {
+            info!(
+                "[gandi] Dry run, would set {} {} record to {}",
+                name, record_type, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let response = self
+            .client
+            .put(&url)
+            .header("Authorization", format!("Apikey {}", *self.config.api_key))
+            .json(&GandiRrset {
+                rrset_values: vec![new_ip.to_string()],
+                rrset_ttl: self.config.ttl.unwrap_or_else(default_ttl),
+            })
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Gandi::create_gandi_error(response).await);
+        }
+
+        let gandi_res: GandiMessage = response.json().await.wrap_err("Failed to read response")?;
+        info!("Successful: {}", gandi_res.message);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GandiError {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GandiMessage {
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GandiRrset {
+    rrset_values: Vec<String>,
+    #[serde(default = "default_ttl")]
+    rrset_ttl: u32,
+}
@@ -0,0 +1,319 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use log::info;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+const SCOPE: &str = "https://www.googleapis.com/auth/ndev.clouddns.readwrite";
+
+pub struct GoogleDns {
+    config: GoogleDnsConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GoogleDnsConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub project_id: String,
+    pub managed_zone: String,
+    /// The service account key, either inline JSON or the path to the downloaded key file.
+    pub service_account_json: Secret<String>,
+    /// TTL to set on the record set. When absent, an existing record set keeps its TTL.
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://dns.googleapis.com/dns/v1".to_string()
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+impl GoogleDns {
+    async fn create_google_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from Google Cloud DNS: {de}",
+            status = status,
+            de = response.json::<GoogleErrorResponse>().await.map_or_else(
+                |e| format!("Unable to read response: {:?}", e),
+                |v| format!("[{}] {}", v.error.code, v.error.message),
+            ),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    fn service_account(&self) -> color_eyre::Result<GoogleServiceAccount> {
+        let json = if self
+            .config
+            .service_account_json
+            .trim_start()
+            .starts_with('{')
+        {
+            self.config.service_account_json.to_string()
+        } else {
+            std::fs::read_to_string(&*self.config.service_account_json).wrap_err_with(|| {
+                format!(
+                    "Failed to read service account key {}",
+                    *self.config.service_account_json
+                )
+            })?
+        };
+        serde_json::from_str(&json).wrap_err("Failed to parse service account key")
+    }
+
+    /// Trades a JWT signed by the service account for an OAuth access token.
+    async fn access_token(&self) -> Result<String, UpdateDnsError> {
+        let account = self.service_account()?;
+        let now = chrono::Utc::now().timestamp();
+        let claims = GoogleJwtClaims {
+            iss: &account.client_email,
+            scope: SCOPE,
+            aud: &account.token_uri,
+            iat: now,
+            exp: now + 3600,
+        };
+        let key = EncodingKey::from_rsa_pem(account.private_key.as_bytes())
+            .wrap_err("Invalid service account private key")?;
+        let assertion = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key)
+            .wrap_err("Failed to sign JWT")?;
+
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer")
+            .append_pair("assertion", &assertion)
+            .finish();
+        let response = self
+            .client
+            .post(&account.token_uri)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        let status = response.status();
+        if !status.is_success() {
+            let report = eyre!(
+                "{status} Error from Google OAuth: {body}",
+                status = status,
+                body = response
+                    .text()
+                    .await
+                    .unwrap_or_else(|e| format!("Unable to read response: {:?}", e)),
+            );
+            // A key that's revoked or for another account is a 400 `invalid_grant`
+            return Err(match status {
+                StatusCode::BAD_REQUEST => UpdateDnsError::Auth(report),
+                _ => UpdateDnsError::from_status(status, report),
+            });
+        }
+
+        let token: GoogleTokenRes = response.json().await.wrap_err("Failed to read response")?;
+        Ok(token.access_token)
+    }
+}
+
+impl UpdateDnsCreator for GoogleDns {
+    type Config = GoogleDnsConfig;
+    const TAG: &'static str = "google_dns";
+    const DESCRIPTION: &'static str = "Google Cloud DNS, by managed zone, with a service account";
+
+    fn from_config(config: Self::Config) -> Self {
+        GoogleDns {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for GoogleDns {
+    fn describe(&self) -> String {
+        format!(
+            "GoogleDns[project={project_id}, zone={managed_zone}]",
+            project_id = &self.config.project_id,
+            managed_zone = &self.config.managed_zone,
+        )
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        // Cloud DNS always uses fully qualified names
+        let fqdn = format!("{}.", name.trim_end_matches('.'));
+        let token = self.access_token().await?;
+
+        // GET the `name` record set of the address's type
+        let response = self
+            .client
+            .get(format!(
+                "{base}/projects/{project_id}/managedZones/{managed_zone}/rrsets",
+                base = self.config.base_url,
+                project_id = &self.config.project_id,
+                managed_zone = &self.config.managed_zone,
+            ))
+            .query(&[("name", fqdn.as_str()), ("type", record_type)])
+            .bearer_auth(&token)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(GoogleDns::create_google_error(response).await);
+        }
+
+        let list: GoogleListRrsetsRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        let existing = match list.rrsets.as_slice() {
+            [] => None,
+            [r] => Some(r),
+            _ => return Err(eyre!("Expected at most one result, got {:?}", list.rrsets).into()),
+        };
+
+        let old_ip = existing
+            .and_then(|r| r.rrdatas.first())
+            .and_then(|ip| ip.parse().ok());
+        let ttl = self
+            .config
+            .ttl
+            .or(existing.map(|r| r.ttl))
+            .unwrap_or_else(default_ttl);
+        if let Some(existing) = existing {
+            if existing.rrdatas == [new_ip.to_string()] && existing.ttl == ttl {
+                info!(
+                    "[google_dns] New IP is the same as existing {} record, skipping update.",
+                    record_type
+                );
+                return Ok(UpdateOutcome::unchanged(new_ip));
+            }
+            info!(
+                "[google_dns] Old {} content was {} with TTL {}",
+                record_type,
+                existing.rrdatas.join(", "),
+                existing.ttl
+            );
+        } else {
+            info!(
+                "[google_dns] No existing {} record, creating one.",
+                record_type
+            );
+        }
+
+        if dry_run {
+            info!(
+                "[google_dns] Dry run, would set {} {} record to {}",
+                fqdn, record_type, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        // Record sets can't be edited in place, the change swaps the old one for the new one
+        let response = self
+            .client
+            .post(format!(
+                "{base}/projects/{project_id}/managedZones/{managed_zone}/changes",
+                base = self.config.base_url,
+                project_id = &self.config.project_id,
+                managed_zone = &self.config.managed_zone,
+            ))
+            .json(&GoogleChangeReq {
+                deletions: existing.into_iter().cloned().collect(),
+                additions: vec![GoogleRrset {
+                    name: fqdn,
+                    record_type: record_type.to_string(),
+                    ttl,
+                    rrdatas: vec![new_ip.to_string()],
+                }],
+            })
+            .bearer_auth(&token)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(GoogleDns::create_google_error(response).await);
+        }
+
+        let change: GoogleChangeRes = response.json().await.wrap_err("Failed to read response")?;
+        info!("Successful: change {} is {}", change.id, change.status);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize)]
+struct GoogleServiceAccount {
+    client_email: String,
+    private_key: String,
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+}
+
+fn default_token_uri() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+#[derive(Serialize)]
+struct GoogleJwtClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct GoogleTokenRes {
+    access_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GoogleErrorResponse {
+    error: GoogleError,
+}
+
+#[derive(Deserialize, Debug)]
+struct GoogleError {
+    code: u16,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GoogleListRrsetsRes {
+    #[serde(default)]
+    rrsets: Vec<GoogleRrset>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct GoogleRrset {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    ttl: u32,
+    rrdatas: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct GoogleChangeReq {
+    deletions: Vec<GoogleRrset>,
+    additions: Vec<GoogleRrset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GoogleChangeRes {
+    id: String,
+    status: String,
+}
@@ -0,0 +1,220 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// Hetzner names records relative to their zone, so the zone's own name is looked up to turn the
+/// name being updated into a record name.
+pub struct Hetzner {
+    config: HetznerConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HetznerConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub api_token: Secret<String>,
+    pub zone_id: String,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://dns.hetzner.com/api/v1".to_string()
+}
+
+impl Hetzner {
+    async fn create_hetzner_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from Hetzner: {de}",
+            status = status,
+            de = response.json::<HetznerErrorResponse>().await.map_or_else(
+                |e| format!("Unable to read response: {:?}", e),
+                |v| format!("[{}] {}", v.error.code, v.error.message),
+            ),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.client
+            .get(format!("{}{}", self.config.base_url, path))
+            .header("Auth-API-Token", self.config.api_token.as_str())
+    }
+
+    fn record_name(zone: &str, name: &str) -> color_eyre::Result<String> {
+        relative_name(name, zone, "@").ok_or_else(|| eyre!("{} is not in zone {}", name, zone))
+    }
+}
+
+impl UpdateDnsCreator for Hetzner {
+    type Config = HetznerConfig;
+    const TAG: &'static str = "hetzner";
+    const DESCRIPTION: &'static str = "Hetzner DNS, by zone id";
+
+    fn from_config(config: Self::Config) -> Self {
+        Hetzner {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Hetzner {
+    fn describe(&self) -> String {
+        format!("Hetzner[zone={zone_id}]", zone_id = &self.config.zone_id)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+
+        let response = self
+            .get(&format!("/zones/{}", self.config.zone_id))
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Hetzner::create_hetzner_error(response).await);
+        }
+        let zone: HetznerZoneRes = response.json().await.wrap_err("Failed to read response")?;
+        let record_name = Hetzner::record_name(&zone.zone.name, &name)?;
+
+        // GET all records in the zone, the API can't filter by name or type
+        let response = self
+            .get("/records")
+            .query(&[("zone_id", &self.config.zone_id)])
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Hetzner::create_hetzner_error(response).await);
+        }
+
+        let list: HetznerListRecordsRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        let matching: Vec<&HetznerRecord> = list
+            .records
+            .iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(&record_name) && r.record_type == record_type)
+            .collect();
+        let record = match matching.as_slice() {
+            [r] => r,
+            _ => return Err(eyre!("Expected exactly one result, got {:?}", matching).into()),
+        };
+
+        if record.value == new_ip.to_string() {
+            info!(
+                "[hetzner] New IP is the same as existing {} record, skipping update.",
+                record_type
+            );
+            return Ok(UpdateOutcome::unchanged(new_ip));
+        }
+
+        let old_ip = record.value.parse().ok();
+        info!("[hetzner] Old {} content was {}", record_type, record.value);
+
+        if dry_run {
+            info!(
+                "[hetzner] Dry run, would update {} from {} to {}",
+                name, record.value, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let response = self
+            .client
+            .put(format!(
+                "{base}/records/{id}",
+                base = self.config.base_url,
+                id = record.id,
+            ))
+            .header("Auth-API-Token", self.config.api_token.as_str())
+            .json(&HetznerUpdateRecordReq {
+                zone_id: &self.config.zone_id,
+                record_type,
+                name: &record.name,
+                value: new_ip.to_string(),
+                ttl: record.ttl,
+            })
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Hetzner::create_hetzner_error(response).await);
+        }
+
+        let hetzner_res: HetznerUpdateRecordRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        info!("Successful: {:?}", hetzner_res.record);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct HetznerErrorResponse {
+    error: HetznerError,
+}
+
+#[derive(Deserialize, Debug)]
+struct HetznerError {
+    code: u16,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct HetznerZoneRes {
+    zone: HetznerZone,
+}
+
+#[derive(Deserialize, Debug)]
+struct HetznerZone {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct HetznerListRecordsRes {
+    #[serde(default)]
+    records: Vec<HetznerRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HetznerUpdateRecordRes {
+    record: HetznerRecord,
+}
+
+#[derive(Deserialize, Debug)]
+struct HetznerRecord {
+    id: String,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    value: String,
+    ttl: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct HetznerUpdateRecordReq<'a> {
+    zone_id: &'a str,
+    #[serde(rename = "type")]
+    record_type: &'a str,
+    name: &'a str,
+    value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+}
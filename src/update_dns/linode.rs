@@ -0,0 +1,213 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// Linode names records relative to their domain, so the domain's own name is looked up to turn
+/// the name being updated into a record name.
+pub struct Linode {
+    config: LinodeConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LinodeConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub token: Secret<String>,
+    pub domain_id: u64,
+    /// TTL to set on the record, the existing one is kept if this is left out.
+    pub ttl: Option<u32>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://api.linode.com/v4".to_string()
+}
+
+impl Linode {
+    async fn create_linode_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from Linode: {de}",
+            status = status,
+            de = response.json::<LinodeErrorResponse>().await.map_or_else(
+                |e| format!("Unable to read response: {:?}", e),
+                |v| v
+                    .errors
+                    .into_iter()
+                    .map(|e| e.reason)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    /// Linode wants the name relative to the domain, empty for the domain itself.
+    fn record_name(domain: &str, name: &str) -> color_eyre::Result<String> {
+        relative_name(name, domain, "").ok_or_else(|| eyre!("{} is not in domain {}", name, domain))
+    }
+}
+
+impl UpdateDnsCreator for Linode {
+    type Config = LinodeConfig;
+    const TAG: &'static str = "linode";
+    const DESCRIPTION: &'static str = "Linode (Akamai) DNS, by domain id";
+
+    fn from_config(config: Self::Config) -> Self {
+        Linode {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Linode {
+    fn describe(&self) -> String {
+        format!(
+            "Linode[domain={domain_id}]",
+            domain_id = self.config.domain_id
+        )
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let domain_url = format!(
+            "{base}/domains/{domain_id}",
+            base = self.config.base_url,
+            domain_id = self.config.domain_id,
+        );
+
+        let response = self
+            .client
+            .get(&domain_url)
+            .bearer_auth(self.config.token.as_str())
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Linode::create_linode_error(response).await);
+        }
+        let domain: LinodeDomain = response.json().await.wrap_err("Failed to read response")?;
+        let record_name = Linode::record_name(&domain.domain, &name)?;
+
+        // GET all records in the domain, the API can't filter by name or type
+        let response = self
+            .client
+            .get(format!("{}/records", domain_url))
+            .bearer_auth(self.config.token.as_str())
+            .query(&[("page_size", "500")])
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Linode::create_linode_error(response).await);
+        }
+
+        let list: LinodeListRecordsRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        let matching: Vec<&LinodeRecord> = list
+            .data
+            .iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(&record_name) && r.record_type == record_type)
+            .collect();
+        let record = match matching.as_slice() {
+            [r] => r,
+            _ => return Err(eyre!("Expected exactly one result, got {:?}", matching).into()),
+        };
+
+        let ttl_sec = self.config.ttl.unwrap_or(record.ttl_sec);
+        if record.target == new_ip.to_string() && record.ttl_sec == ttl_sec {
+            info!(
+                "[linode] New IP is the same as existing {} record, skipping update.",
+                record_type
+            );
+            return Ok(UpdateOutcome::unchanged(new_ip));
+        }
+
+        let old_ip = record.target.parse().ok();
+        info!(
+            "[linode] Old {} content was {} with TTL {}",
+            record_type, record.target, record.ttl_sec
+        );
+
+        if dry_run {
+            info!(
+                "[linode] Dry run, would update {} from {} to {}",
+                name, record.target, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let response = self
+            .client
+            .put(format!("{}/records/{}", domain_url, record.id))
+            .bearer_auth(self.config.token.as_str())
+            .json(&LinodeUpdateRecordReq {
+                target: new_ip.to_string(),
+                ttl_sec,
+            })
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Linode::create_linode_error(response).await);
+        }
+
+        let linode_res: LinodeRecord = response.json().await.wrap_err("Failed to read response")?;
+        info!("Successful: {:?}", linode_res);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct LinodeErrorResponse {
+    errors: Vec<LinodeError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LinodeError {
+    reason: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct LinodeDomain {
+    domain: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct LinodeListRecordsRes {
+    data: Vec<LinodeRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LinodeRecord {
+    id: u64,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    target: String,
+    ttl_sec: u32,
+}
+
+#[derive(Serialize)]
+struct LinodeUpdateRecordReq {
+    target: String,
+    ttl_sec: u32,
+}
@@ -1,2 +1,21 @@
-pub(crate) mod api;
-pub(crate) mod cloudflare;
+pub mod api;
+pub mod cloudflare;
+pub mod desec;
+pub mod digitalocean;
+pub mod duckdns;
+pub mod dyndns2;
+pub mod dynu;
+pub mod gandi;
+pub mod google_dns;
+pub mod hetzner;
+pub mod linode;
+pub mod mythic;
+pub mod namecheap;
+pub mod ovh;
+pub mod porkbun;
+pub mod powerdns;
+pub mod registry;
+pub mod retry;
+pub mod route53;
+pub mod stdout;
+pub mod vultr;
@@ -0,0 +1,260 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// Mythic Beasts' DNS API v2, which replaces every record of the host and type at once.
+pub struct Mythic {
+    config: MythicConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MythicConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub key_id: String,
+    pub secret: Secret<String>,
+    /// The zone's name, e.g. `example.com`.
+    pub zone: String,
+    /// The host within the zone, `@` for the zone itself. Defaults to the name being updated less
+    /// the zone.
+    pub host: Option<String>,
+    /// TTL for the record that's written.
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://api.mythic-beasts.com".to_string()
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+impl Mythic {
+    async fn create_mythic_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from Mythic Beasts: {de}",
+            status = status,
+            de = response
+                .json::<MythicErrorRes>()
+                .await
+                .map_or_else(|e| format!("Unable to read response: {:?}", e), |v| v.error),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    fn record_url(&self, host: &str, record_type: &str) -> String {
+        format!(
+            "{base}/dns/v2/zones/{zone}/records/{host}/{record_type}",
+            base = self.config.base_url,
+            zone = self.config.zone.trim_end_matches('.'),
+            host = host,
+            record_type = record_type,
+        )
+    }
+
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        builder.basic_auth(&self.config.key_id, Some(self.config.secret.as_str()))
+    }
+
+    fn host(&self, name: &str) -> color_eyre::Result<String> {
+        if let Some(host) = &self.config.host {
+            return Ok(host.clone());
+        }
+        relative_name(name, &self.config.zone, "@")
+            .ok_or_else(|| eyre!("{} is not in zone {}", name, self.config.zone))
+    }
+}
+
+impl UpdateDnsCreator for Mythic {
+    type Config = MythicConfig;
+    const TAG: &'static str = "mythic";
+    const DESCRIPTION: &'static str = "Mythic Beasts' DNS API, by zone and host";
+
+    fn from_config(config: Self::Config) -> Self {
+        Mythic {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Mythic {
+    fn describe(&self) -> String {
+        format!("Mythic[zone={zone}]", zone = &self.config.zone)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let host = self.host(&name)?;
+        let url = self.record_url(&host, record_type);
+
+        let response = self
+            .request(self.client.get(&url))
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        let existing = if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Vec::new()
+        } else if response.status().is_success() {
+            let list: MythicRecordsRes =
+                response.json().await.wrap_err("Failed to read response")?;
+            list.records
+        } else {
+            return Err(Mythic::create_mythic_error(response).await);
+        };
+
+        let old_ip = existing.first().and_then(|record| record.data.parse().ok());
+        if let [record] = existing.as_slice() {
+            if old_ip == Some(new_ip) && record.ttl == self.config.ttl.unwrap_or_else(default_ttl) {
+                info!(
+                    "[mythic] New IP is the same as existing for {}, nothing changed.",
+                    name
+                );
+                return Ok(UpdateOutcome::unchanged(new_ip));
+            }
+        }
+
+        if dry_run {
+            info!(
+                "[mythic] Dry run, would set {} record {} in {} to {}",
+                record_type, host, self.config.zone, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let body = MythicRecordsReq {
+            records: vec![MythicRecord {
+                host: host.clone(),
+                ttl: self.config.ttl.unwrap_or_else(default_ttl),
+                record_type: record_type.to_string(),
+                data: new_ip.to_string(),
+            }],
+        };
+        let response = self
+            .request(self.client.put(&url))
+            .json(&body)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Mythic::create_mythic_error(response).await);
+        }
+        let changes: MythicChangesRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        Ok(changes.outcome(&name, old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct MythicErrorRes {
+    error: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MythicRecordsRes {
+    #[serde(default)]
+    records: Vec<MythicRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MythicRecord {
+    host: String,
+    ttl: u32,
+    #[serde(rename = "type")]
+    record_type: String,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct MythicRecordsReq {
+    records: Vec<MythicRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MythicChangesRes {
+    #[serde(default)]
+    records_added: u32,
+    #[serde(default)]
+    records_removed: u32,
+}
+
+impl MythicChangesRes {
+    /// Replacing a record with an identical one adds and removes nothing.
+    fn outcome(&self, name: &str, old_ip: Option<IpAddr>, new_ip: IpAddr) -> UpdateOutcome {
+        if self.records_added == 0 && self.records_removed == 0 {
+            info!(
+                "[mythic] New IP is the same as existing for {}, nothing changed.",
+                name
+            );
+            return UpdateOutcome::unchanged(new_ip);
+        }
+        info!("Successful: {} is now {}", name, new_ip);
+        UpdateOutcome::written(old_ip, new_ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_are_relative_to_the_zone() {
+        let mythic = Mythic::from_config(
+            serde_json::from_value(serde_json::json!({
+                "key_id": "k",
+                "secret": "s",
+                "zone": "example.com",
+            }))
+            .unwrap(),
+        );
+
+        assert_eq!(mythic.host("home.example.com").unwrap(), "home");
+        assert_eq!(mythic.host("Example.com.").unwrap(), "@");
+        assert_eq!(
+            mythic.record_url("home", "A"),
+            "https://api.mythic-beasts.com/dns/v2/zones/example.com/records/home/A"
+        );
+        assert!(mythic.host("home.example.org").is_err());
+    }
+
+    #[test]
+    fn change_counts_become_outcomes() {
+        let changes = |json| serde_json::from_str::<MythicChangesRes>(json).unwrap();
+        let old = Some("198.51.100.1".parse().unwrap());
+        let new = "203.0.113.5".parse().unwrap();
+
+        assert_eq!(
+            changes(r#"{"records_added": 1, "records_removed": 1, "message": "1 record added"}"#)
+                .outcome("home.example.com", old, new),
+            UpdateOutcome::written(old, new)
+        );
+        assert_eq!(
+            changes(r#"{"records_added": 0, "records_removed": 0}"#).outcome(
+                "home.example.com",
+                old,
+                new
+            ),
+            UpdateOutcome::unchanged(new)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::{info, warn};
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// Namecheap's Dynamic DNS updates exactly one host, so unlike the other providers the record
+/// comes from the config rather than the name being updated. When `host` isn't configured it's
+/// derived from that name by removing `domain`, and when it is the name is only used in logs.
+pub struct Namecheap {
+    config: NamecheapConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NamecheapConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub domain: String,
+    /// The label to update in `domain`, `@` for the domain itself.
+    pub host: Option<String>,
+    /// The Dynamic DNS password from the domain's Advanced DNS page, not the account password.
+    pub password: Secret<String>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://dynamicdns.park-your-domain.com".to_string()
+}
+
+impl Namecheap {
+    fn host(&self, name: &str) -> color_eyre::Result<String> {
+        let name = name.trim_end_matches('.');
+        if let Some(host) = &self.config.host {
+            let expected = match host.as_str() {
+                "@" => self.config.domain.clone(),
+                _ => format!("{}.{}", host, self.config.domain),
+            };
+            if !name.eq_ignore_ascii_case(&expected) {
+                warn!(
+                    "[namecheap] Updating configured host {} instead of {}",
+                    expected, name
+                );
+            }
+            return Ok(host.clone());
+        }
+
+        relative_name(name, &self.config.domain, "@")
+            .ok_or_else(|| eyre!("{} is not in domain {}", name, self.config.domain))
+    }
+}
+
+impl UpdateDnsCreator for Namecheap {
+    type Config = NamecheapConfig;
+    const TAG: &'static str = "namecheap";
+    const DESCRIPTION: &'static str = "Namecheap's Dynamic DNS, one host per entry";
+
+    fn from_config(config: Self::Config) -> Self {
+        Namecheap {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Namecheap {
+    fn describe(&self) -> String {
+        format!("Namecheap[domain={domain}]", domain = &self.config.domain)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        if new_ip.is_ipv6() {
+            info!(
+                "[namecheap] Dynamic DNS only supports A records, skipping {}",
+                new_ip
+            );
+            return Ok(UpdateOutcome::not_written(None, new_ip));
+        }
+        let host = self.host(&name)?;
+
+        // There's no way to read the current value, so this always updates
+        if dry_run {
+            info!(
+                "[namecheap] Dry run, would update {} in {} to {}",
+                host, self.config.domain, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(None, new_ip));
+        }
+
+        let response = self
+            .client
+            .get(format!("{base}/update", base = self.config.base_url))
+            .query(&[
+                ("host", host.as_str()),
+                ("domain", &self.config.domain),
+                ("password", self.config.password.as_str()),
+                ("ip", &new_ip.to_string()),
+            ])
+            .send()
+            .await
+            // The URL has the password in it
+            .map_err(reqwest::Error::without_url)
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(UpdateDnsError::from_status(
+                response.status(),
+                eyre!("{} Error from Namecheap", response.status()),
+            ));
+        }
+
+        // Failures still come back as 200, only the body says what happened
+        let body = response
+            .text()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .wrap_err("Failed to read response")?;
+        let nc_res: NamecheapRes =
+            quick_xml::de::from_str(&body).wrap_err("Failed to read response")?;
+        if nc_res.err_count > 0 {
+            return Err(eyre!(
+                "Error from Namecheap: {}",
+                nc_res
+                    .errors
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into());
+        }
+        info!("Successful: {} is now {}", host, new_ip);
+
+        Ok(UpdateOutcome::written(None, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct NamecheapRes {
+    #[serde(rename = "ErrCount")]
+    err_count: u32,
+    /// Keyed `Err1`, `Err2`, ...
+    #[serde(default)]
+    errors: BTreeMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failed_requests_dont_show_the_password() {
+        let namecheap = Namecheap::from_config(
+            serde_json::from_value(serde_json::json!({
+                // Nothing listens on port 1, so the connection is refused
+                "base_url": "http://127.0.0.1:1",
+                "domain": "example.com",
+                "password": "ddns-password-value",
+            }))
+            .unwrap(),
+        );
+
+        let error = namecheap
+            .update_dns(
+                "home.example.com".to_string(),
+                "203.0.113.5".parse().unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        let shown = format!("{:?} {:#}", error, error);
+        assert!(shown.contains("Failed to send request"), "{}", shown);
+        assert!(!shown.contains("ddns-password-value"), "{}", shown);
+    }
+}
@@ -0,0 +1,247 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, Method, Response};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use url::Url;
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// OVH signs each request with the application secret and consumer key, and only publishes record
+/// changes once the zone is refreshed.
+pub struct Ovh {
+    config: OvhConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OvhConfig {
+    /// The API for the account's region, e.g. `https://ca.api.ovh.com/1.0`.
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+    pub application_key: Secret<String>,
+    pub application_secret: Secret<String>,
+    pub consumer_key: Secret<String>,
+    pub zone: String,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_endpoint() -> String {
+    "https://eu.api.ovh.com/1.0".to_string()
+}
+
+impl Ovh {
+    async fn create_ovh_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from OVH: {de}",
+            status = status,
+            de = response.json::<OvhError>().await.map_or_else(
+                |e| format!("Unable to read response: {:?}", e),
+                |v| v.message
+            ),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    /// OVH wants the name relative to the zone, empty for the zone itself.
+    fn sub_domain(&self, name: &str) -> color_eyre::Result<String> {
+        relative_name(name, &self.config.zone, "")
+            .ok_or_else(|| eyre!("{} is not in zone {}", name, self.config.zone))
+    }
+
+    fn zone_url(&self, path: &str) -> color_eyre::Result<Url> {
+        Url::parse(&format!(
+            "{endpoint}/domain/zone/{zone}{path}",
+            endpoint = self.config.endpoint,
+            zone = &self.config.zone,
+            path = path,
+        ))
+        .wrap_err("Invalid OVH endpoint")
+    }
+
+    async fn send_signed(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<String>,
+    ) -> color_eyre::Result<Response> {
+        let timestamp = Utc::now().timestamp();
+        let payload = body.as_deref().unwrap_or("");
+        let signature = signature(
+            self.config.application_secret.as_str(),
+            self.config.consumer_key.as_str(),
+            method.as_str(),
+            url.as_str(),
+            payload,
+            timestamp,
+        );
+
+        let request = self
+            .client
+            .request(method, url)
+            .header("X-Ovh-Application", self.config.application_key.as_str())
+            .header("X-Ovh-Consumer", self.config.consumer_key.as_str())
+            .header("X-Ovh-Timestamp", timestamp.to_string())
+            .header("X-Ovh-Signature", signature);
+        let request = match body {
+            Some(body) => request
+                .header("Content-Type", "application/json")
+                .body(body),
+            None => request,
+        };
+        let response = request.send().await.wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Ovh::create_ovh_error(response).await.into());
+        }
+        Ok(response)
+    }
+}
+
+/// The signature covers the exact URL and body sent, so both must be passed here as they go out.
+fn signature(
+    application_secret: &str,
+    consumer_key: &str,
+    method: &str,
+    url: &str,
+    body: &str,
+    timestamp: i64,
+) -> String {
+    let to_sign = format!(
+        "{}+{}+{}+{}+{}+{}",
+        application_secret, consumer_key, method, url, body, timestamp
+    );
+    format!("$1${}", hex::encode(Sha1::digest(to_sign.as_bytes())))
+}
+
+impl UpdateDnsCreator for Ovh {
+    type Config = OvhConfig;
+    const TAG: &'static str = "ovh";
+    const DESCRIPTION: &'static str = "OVH DNS zones, with an application key and consumer key";
+
+    fn from_config(config: Self::Config) -> Self {
+        Ovh {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Ovh {
+    fn describe(&self) -> String {
+        format!("OVH[zone={zone}]", zone = &self.config.zone)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let sub_domain = self.sub_domain(&name)?;
+
+        // GET the ids of matching records, then each record itself
+        let mut url = self.zone_url("/record")?;
+        url.query_pairs_mut()
+            .append_pair("fieldType", record_type)
+            .append_pair("subDomain", &sub_domain);
+        let ids: Vec<u64> = self
+            .send_signed(Method::GET, url, None)
+            .await?
+            .json()
+            .await
+            .wrap_err("Failed to read response")?;
+        let id = match ids.as_slice() {
+            [id] => *id,
+            _ => return Err(eyre!("Expected exactly one result, got {:?}", ids).into()),
+        };
+        let record: OvhRecord = self
+            .send_signed(
+                Method::GET,
+                self.zone_url(&format!("/record/{}", id))?,
+                None,
+            )
+            .await?
+            .json()
+            .await
+            .wrap_err("Failed to read response")?;
+
+        if record.target == new_ip.to_string() {
+            info!(
+                "[ovh] New IP is the same as existing {} record, skipping update.",
+                record_type
+            );
+            return Ok(UpdateOutcome::unchanged(new_ip));
+        }
+
+        let old_ip = record.target.parse().ok();
+        info!("[ovh] Old {} content was {}", record_type, record.target);
+
+        if dry_run {
+            info!(
+                "[ovh] Dry run, would update {} from {} to {}",
+                name, record.target, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let body = serde_json::to_string(&OvhUpdateRecordReq {
+            target: new_ip.to_string(),
+        })
+        .wrap_err("Failed to write request")?;
+        self.send_signed(
+            Method::PUT,
+            self.zone_url(&format!("/record/{}", id))?,
+            Some(body),
+        )
+        .await?;
+        self.send_signed(Method::POST, self.zone_url("/refresh")?, None)
+            .await?;
+        info!("Successful: updated record {} and refreshed the zone", id);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct OvhError {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OvhRecord {
+    target: String,
+}
+
+#[derive(Serialize)]
+struct OvhUpdateRecordReq {
+    target: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_matches_known_value() {
+        let signature = signature(
+            "EXEMPLESECRET",
+            "MtSwSrPpNjqfVSmJhLbPyr2i45lSDRU1",
+            "PUT",
+            "https://eu.api.ovh.com/1.0/domain/zone/example.com/record/1234",
+            r#"{"target":"203.0.113.5"}"#,
+            1366560945,
+        );
+
+        assert_eq!(signature, "$1$c9f11b4b51febaab383215c0c824573146bf25d7");
+    }
+}
@@ -0,0 +1,193 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// Porkbun takes the credentials in the body of every request, so even reads are POSTs.
+pub struct Porkbun {
+    config: PorkbunConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PorkbunConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub api_key: Secret<String>,
+    pub secret_api_key: Secret<String>,
+    pub domain: String,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://porkbun.com/api/json/v3".to_string()
+}
+
+impl Porkbun {
+    /// Porkbun wants the name relative to the domain, empty for the domain itself.
+    fn subdomain(&self, name: &str) -> color_eyre::Result<String> {
+        relative_name(name, &self.config.domain, "")
+            .ok_or_else(|| eyre!("{} is not in domain {}", name, self.config.domain))
+    }
+
+    /// POSTs to `path` and checks the `status` Porkbun includes in every response.
+    async fn post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        content: Option<String>,
+    ) -> Result<T, UpdateDnsError> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.config.base_url, path))
+            .json(&PorkbunReq {
+                apikey: self.config.api_key.as_str(),
+                secretapikey: self.config.secret_api_key.as_str(),
+                content,
+            })
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        let status = response.status();
+        if !status.is_success() {
+            let report = eyre!(
+                "{status} Error from Porkbun: {de}",
+                status = status,
+                de = response.json::<PorkbunError>().await.map_or_else(
+                    |e| format!("Unable to read response: {:?}", e),
+                    |v| format!("{} {}", v.status, v.message.unwrap_or_default())
+                ),
+            );
+            return Err(UpdateDnsError::from_status(status, report));
+        }
+        let porkbun_res: PorkbunRes<T> =
+            response.json().await.wrap_err("Failed to read response")?;
+        match porkbun_res.body {
+            Some(body) if porkbun_res.status == "SUCCESS" => Ok(body),
+            _ => Err(eyre!(
+                "{status} Error from Porkbun: {res_status} {message}",
+                status = status,
+                res_status = porkbun_res.status,
+                message = porkbun_res.message.unwrap_or_default(),
+            )
+            .into()),
+        }
+    }
+}
+
+impl UpdateDnsCreator for Porkbun {
+    type Config = PorkbunConfig;
+    const TAG: &'static str = "porkbun";
+    const DESCRIPTION: &'static str = "Porkbun DNS, by domain";
+
+    fn from_config(config: Self::Config) -> Self {
+        Porkbun {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Porkbun {
+    fn describe(&self) -> String {
+        format!("Porkbun[domain={domain}]", domain = &self.config.domain)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let name_type = format!(
+            "{domain}/{record_type}/{subdomain}",
+            domain = &self.config.domain,
+            record_type = record_type,
+            subdomain = self.subdomain(&name)?,
+        );
+
+        let list: PorkbunRecords = self
+            .post(&format!("/dns/retrieveByNameType/{}", name_type), None)
+            .await?;
+        let record = match list.records.as_slice() {
+            [r] => r,
+            _ => return Err(eyre!("Expected exactly one result, got {:?}", list.records).into()),
+        };
+
+        if record.content == new_ip.to_string() {
+            info!(
+                "[porkbun] New IP is the same as existing {} record, skipping update.",
+                record_type
+            );
+            return Ok(UpdateOutcome::unchanged(new_ip));
+        }
+
+        let old_ip = record.content.parse().ok();
+        info!(
+            "[porkbun] Old {} content was {}",
+            record_type, record.content
+        );
+
+        if dry_run {
+            info!(
+                "[porkbun] Dry run, would update {} from {} to {}",
+                name, record.content, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let _: serde_json::Value = self
+            .post(
+                &format!("/dns/editByNameType/{}", name_type),
+                Some(new_ip.to_string()),
+            )
+            .await?;
+        info!("Successful: updated record {}", record.id);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Serialize)]
+struct PorkbunReq<'a> {
+    apikey: &'a str,
+    secretapikey: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PorkbunRes<T> {
+    status: String,
+    message: Option<String>,
+    #[serde(flatten)]
+    body: Option<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PorkbunError {
+    status: String,
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PorkbunRecords {
+    #[serde(default)]
+    records: Vec<PorkbunRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PorkbunRecord {
+    id: String,
+    content: String,
+}
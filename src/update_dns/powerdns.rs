@@ -0,0 +1,332 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+/// A self-hosted PowerDNS authoritative server, through its HTTP API. The record's RRset is
+/// replaced as a whole, so it ends up holding only the new address.
+pub struct PowerDns {
+    config: PowerDnsConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PowerDnsConfig {
+    /// Where the API is served, e.g. `http://ns1.example.com:8081`.
+    pub base_url: String,
+    pub api_key: Secret<String>,
+    #[serde(default = "default_server_id")]
+    pub server_id: String,
+    /// The zone's name, e.g. `example.com`.
+    pub zone: String,
+    /// TTL to set on the RRset. When absent, an existing RRset keeps its TTL.
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_server_id() -> String {
+    "localhost".to_string()
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+impl PowerDns {
+    async fn create_powerdns_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from PowerDNS: {de}",
+            status = status,
+            de = response
+                .json::<PowerDnsErrorRes>()
+                .await
+                .map_or_else(|e| format!("Unable to read response: {:?}", e), |v| v.error),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    fn zone_url(&self) -> String {
+        format!(
+            "{base}/api/v1/servers/{server_id}/zones/{zone}",
+            base = self.config.base_url,
+            server_id = self.config.server_id,
+            zone = absolute(&self.config.zone),
+        )
+    }
+
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        builder.header("X-API-Key", self.config.api_key.as_str())
+    }
+
+    /// PowerDNS names are absolute, with the trailing dot.
+    fn rrset_name(&self, name: &str) -> color_eyre::Result<String> {
+        let name = absolute(name);
+        let zone = absolute(&self.config.zone);
+        let lower = name.to_ascii_lowercase();
+        let zone_lower = zone.to_ascii_lowercase();
+        if lower == zone_lower || lower.ends_with(&format!(".{}", zone_lower)) {
+            Ok(name)
+        } else {
+            Err(eyre!(
+                "{} is not in zone {}",
+                name.trim_end_matches('.'),
+                self.config.zone
+            ))
+        }
+    }
+}
+
+fn absolute(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+impl UpdateDnsCreator for PowerDns {
+    type Config = PowerDnsConfig;
+    const TAG: &'static str = "powerdns";
+    const DESCRIPTION: &'static str = "A self-hosted PowerDNS authoritative server, by zone";
+
+    fn from_config(config: Self::Config) -> Self {
+        PowerDns {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for PowerDns {
+    fn describe(&self) -> String {
+        format!("PowerDns[zone={zone}]", zone = &self.config.zone)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let rrset_name = self.rrset_name(&name)?;
+
+        let response = self
+            .request(self.client.get(self.zone_url()))
+            .query(&[
+                ("rrset_name", rrset_name.as_str()),
+                ("rrset_type", record_type),
+            ])
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(PowerDns::create_powerdns_error(response).await);
+        }
+        let zone: PowerDnsZoneRes = response.json().await.wrap_err("Failed to read response")?;
+        // Older servers ignore the filter and return every RRset
+        let existing = zone.rrsets.into_iter().find(|rrset| {
+            rrset.name.eq_ignore_ascii_case(&rrset_name) && rrset.record_type == record_type
+        });
+
+        let old_ip = existing
+            .as_ref()
+            .and_then(|rrset| rrset.records.first())
+            .and_then(|record| record.content.parse().ok());
+        let ttl = self
+            .config
+            .ttl
+            .or(existing.as_ref().map(|rrset| rrset.ttl))
+            .unwrap_or_else(default_ttl);
+        if let Some(rrset) = &existing {
+            if let [record] = rrset.records.as_slice() {
+                if old_ip == Some(new_ip) && !record.disabled && rrset.ttl == ttl {
+                    info!(
+                        "[powerdns] New IP is the same as existing for {}, nothing changed.",
+                        name
+                    );
+                    return Ok(UpdateOutcome::unchanged(new_ip));
+                }
+            }
+        }
+
+        if dry_run {
+            info!(
+                "[powerdns] Dry run, would set {} record {} to {}",
+                record_type, rrset_name, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let body = PowerDnsPatchReq {
+            rrsets: vec![PowerDnsRrsetChange {
+                name: &rrset_name,
+                record_type,
+                ttl,
+                changetype: "REPLACE",
+                records: vec![PowerDnsRecord {
+                    content: new_ip.to_string(),
+                    disabled: false,
+                }],
+            }],
+        };
+        let response = self
+            .request(self.client.patch(self.zone_url()))
+            .json(&body)
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(PowerDns::create_powerdns_error(response).await);
+        }
+        info!("Successful: {} is now {}", name, new_ip);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct PowerDnsErrorRes {
+    error: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PowerDnsZoneRes {
+    #[serde(default)]
+    rrsets: Vec<PowerDnsRrset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PowerDnsRrset {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    ttl: u32,
+    records: Vec<PowerDnsRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PowerDnsRecord {
+    content: String,
+    #[serde(default)]
+    disabled: bool,
+}
+
+#[derive(Serialize)]
+struct PowerDnsPatchReq<'a> {
+    rrsets: Vec<PowerDnsRrsetChange<'a>>,
+}
+
+#[derive(Serialize)]
+struct PowerDnsRrsetChange<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    record_type: &'a str,
+    ttl: u32,
+    changetype: &'a str,
+    records: Vec<PowerDnsRecord>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_become_absolute_within_the_zone() {
+        let powerdns = PowerDns::from_config(
+            serde_json::from_value(serde_json::json!({
+                "base_url": "http://pdns.test:8081",
+                "api_key": "k",
+                "zone": "example.com",
+            }))
+            .unwrap(),
+        );
+
+        assert_eq!(
+            powerdns.zone_url(),
+            "http://pdns.test:8081/api/v1/servers/localhost/zones/example.com."
+        );
+        assert_eq!(
+            powerdns.rrset_name("home.example.com").unwrap(),
+            "home.example.com."
+        );
+        assert_eq!(powerdns.rrset_name("example.com.").unwrap(), "example.com.");
+        assert_eq!(
+            powerdns
+                .rrset_name("home.example.org")
+                .unwrap_err()
+                .to_string(),
+            "home.example.org is not in zone example.com"
+        );
+    }
+
+    /// Answers `connections` requests on a local port with the zone holding `rrsets`.
+    fn zone_stub(connections: usize, rrsets: serde_json::Value) -> String {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let body = serde_json::json!({ "rrsets": rrsets }).to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn configured_ttl_wins_over_the_existing_one() {
+        let base_url = zone_stub(
+            2,
+            serde_json::json!([{
+                "name": "home.example.com.",
+                "type": "A",
+                "ttl": 3600,
+                "records": [{"content": "203.0.113.5", "disabled": false}],
+            }]),
+        );
+        let powerdns = |ttl: Option<u32>| {
+            PowerDns::from_config(
+                serde_json::from_value(serde_json::json!({
+                    "base_url": base_url,
+                    "api_key": "k",
+                    "zone": "example.com",
+                    "ttl": ttl,
+                }))
+                .unwrap(),
+            )
+        };
+        let new_ip = "203.0.113.5".parse().unwrap();
+
+        let kept = powerdns(None)
+            .update_dns("home.example.com".to_string(), new_ip, true)
+            .await
+            .unwrap();
+        let changed = powerdns(Some(300))
+            .update_dns("home.example.com".to_string(), new_ip, true)
+            .await
+            .unwrap();
+
+        assert_eq!(kept, UpdateOutcome::unchanged(new_ip));
+        assert_eq!(changed, UpdateOutcome::not_written(Some(new_ip), new_ip));
+    }
+}
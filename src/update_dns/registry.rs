@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Mutex;
+
+use crate::update_dns::api::UpdateDns;
+
+/// Builds a provider from the rest of its config entry, everything but the `provider` tag.
+pub type ProviderConstructor = fn(serde_json::Value) -> color_eyre::Result<Box<dyn UpdateDns>>;
+
+static PROVIDERS: Mutex<BTreeMap<&'static str, ProviderConstructor>> = Mutex::new(BTreeMap::new());
+
+/// Makes `{provider: <tag>, ...}` entries in the secrets file use `constructor`. Providers outside
+/// this crate register themselves this way before the config is loaded. A later registration for
+/// the same tag replaces the earlier one, but the built-in providers' tags always mean them.
+pub fn register(tag: &'static str, constructor: ProviderConstructor) {
+    PROVIDERS
+        .lock()
+        .expect("provider registry poisoned")
+        .insert(tag, constructor);
+}
+
+/// `None` if nothing is registered for `tag`.
+pub(crate) fn construct(
+    tag: &str,
+    config: serde_json::Value,
+) -> Option<color_eyre::Result<Box<dyn UpdateDns>>> {
+    let constructor = *PROVIDERS
+        .lock()
+        .expect("provider registry poisoned")
+        .get(tag)?;
+    Some(constructor(config))
+}
+
+pub(crate) fn registered_tags() -> Vec<&'static str> {
+    PROVIDERS
+        .lock()
+        .expect("provider registry poisoned")
+        .keys()
+        .copied()
+        .collect()
+}
+
+/// A provider built through the registry, which handles its own settings.
+pub struct RegisteredProvider {
+    pub tag: String,
+    pub dns: Box<dyn UpdateDns>,
+}
+
+impl Debug for RegisteredProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisteredProvider")
+            .field("tag", &self.tag)
+            .field("dns", &self.dns.describe())
+            .finish()
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use color_eyre::eyre::WrapErr;
+use log::{error, warn};
+use rand::Rng;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::http::{HttpClient, HttpError, HttpErrorKind, HttpRequest, HttpResponse};
+use crate::update_dns::api::UpdateDnsError;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RetryConfig {
+    /// Retries after the first attempt, so `0` disables retrying.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each one after.
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: default_max_retries(),
+            base_delay_ms: default_base_delay_ms(),
+        }
+    }
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_base_delay_ms() -> u64 {
+    500
+}
+
+/// The most a `Retry-After` is waited for, so a daemon isn't stalled for long by one response.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+impl RetryConfig {
+    /// Exponential backoff with jitter, somewhere between half and all of `base * 2^retry`.
+    fn delay(&self, retry: u32) -> Duration {
+        let max = self
+            .base_delay_ms
+            .saturating_mul(2u64.saturating_pow(retry))
+            .max(1);
+        Duration::from_millis(rand::thread_rng().gen_range(max / 2..=max))
+    }
+}
+
+/// Connection problems, 5xx and 429 are worth trying again. Other 4xx won't get any better.
+fn is_transient(result: &Result<HttpResponse, HttpError>) -> bool {
+    match result {
+        Ok(response) => {
+            response.status.is_server_error() || response.status == StatusCode::TOO_MANY_REQUESTS
+        }
+        Err(e) => matches!(e.kind, HttpErrorKind::Connect | HttpErrorKind::Timeout),
+    }
+}
+
+/// Sends `request` until it gives a successful response, a non-transient failure, or the retries
+/// run out. Unsuccessful responses are turned into errors with `into_error`, under the
+/// [UpdateDnsError] kind for their status.
+///
+/// Each failed attempt that will be retried is a warning. Running out of retries is an error, and
+/// the error returned then says how many attempts were made, so it reads differently from a
+/// failure that wasn't worth retrying.
+pub(crate) async fn send_with_retry<E>(
+    config: &RetryConfig,
+    client: &dyn HttpClient,
+    request: &HttpRequest,
+    into_error: E,
+) -> color_eyre::Result<HttpResponse>
+where
+    E: Fn(HttpResponse) -> color_eyre::Report,
+{
+    let into_error = |response: HttpResponse| -> color_eyre::Report {
+        UpdateDnsError::from_status(response.status, into_error(response)).into()
+    };
+    let attempts = config.max_retries + 1;
+    let mut retry = 0;
+    let mut rate_limited_for = None;
+    loop {
+        let result = client.send(request).await;
+        let transient = is_transient(&result);
+        if retry < config.max_retries && transient {
+            // A rate limit says when it will be lifted, which beats guessing with backoff
+            let delay = match &result {
+                Ok(response) if response.status == StatusCode::TOO_MANY_REQUESTS => {
+                    match response.retry_after {
+                        Some(retry_after) => {
+                            let delay = retry_after.min(MAX_RETRY_AFTER);
+                            rate_limited_for = Some(delay);
+                            delay
+                        }
+                        None => config.delay(retry),
+                    }
+                }
+                _ => config.delay(retry),
+            };
+            warn!(
+                "Attempt {}/{} failed with {}, retrying in {:.1?}",
+                retry + 1,
+                attempts,
+                describe_failure(&result),
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            retry += 1;
+            continue;
+        }
+
+        let attempted = retry + 1;
+        let gave_up = transient && retry > 0;
+        if gave_up {
+            error!(
+                "Attempt {}/{} failed with {}, giving up",
+                attempted,
+                attempts,
+                describe_failure(&result)
+            );
+        }
+        return match result {
+            Ok(response) if response.status.is_success() => Ok(response),
+            Ok(response) if response.status == StatusCode::TOO_MANY_REQUESTS => {
+                let error = into_error(response);
+                Err(match rate_limited_for {
+                    Some(delay) => error.wrap_err(format!(
+                        "Rate limited, retried after {} seconds",
+                        delay.as_secs()
+                    )),
+                    None => error.wrap_err("Rate limited"),
+                })
+            }
+            Ok(response) => Err(into_error(response)),
+            Err(e) => Err(e.into()),
+        }
+        .wrap_err_with(|| {
+            if gave_up {
+                format!("Gave up after {} attempts", attempted)
+            } else {
+                format!("Request failed after {} attempt(s)", attempted)
+            }
+        });
+    }
+}
+
+/// A failed attempt as it's logged, the status of a response or the error and its cause.
+fn describe_failure(result: &Result<HttpResponse, HttpError>) -> String {
+    match result {
+        Ok(response) => response.status.to_string(),
+        Err(e) => match std::error::Error::source(e) {
+            Some(source) => format!("{}: {}", e, source),
+            None => e.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre::eyre;
+
+    use super::*;
+    use crate::http::mock::MockHttpClient;
+
+    #[tokio::test]
+    async fn running_out_of_retries_reads_differently_from_a_failure() {
+        let config = &RetryConfig {
+            max_retries: 1,
+            base_delay_ms: 0,
+        };
+        let send = |client: MockHttpClient| async move {
+            let error = send_with_retry(
+                config,
+                &client,
+                &HttpRequest::get("https://dns.test/records"),
+                |response| eyre!("{} from the provider", response.status),
+            )
+            .await
+            .unwrap_err();
+            format!("{:#}", error)
+        };
+
+        assert_eq!(
+            send(MockHttpClient::default().respond(503, "").respond(503, "")).await,
+            "Gave up after 2 attempts: 503 Service Unavailable from the provider"
+        );
+        assert_eq!(
+            send(MockHttpClient::default().respond(404, "")).await,
+            "Request failed after 1 attempt(s): 404 Not Found from the provider"
+        );
+    }
+}
@@ -0,0 +1,573 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, ContextCompat, WrapErr};
+use hmac::{Hmac, Mac};
+use log::info;
+use reqwest::{Client, Response};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+const SERVICE: &str = "route53";
+
+pub struct Route53 {
+    config: Route53Config,
+    signer: SigV4Signer,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Route53Config {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub hosted_zone_id: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret<String>,
+    /// Route 53 is global, but requests are still signed against a region.
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// TTL to set on the record set. When absent, an existing record set keeps its TTL.
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://route53.amazonaws.com".to_string()
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+impl Route53 {
+    async fn create_route53_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from Route 53: {de}",
+            status = status,
+            de = response
+                .text()
+                .await
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|body| {
+                    quick_xml::de::from_str::<Route53ErrorResponse>(&body)
+                        .map_err(|e| format!("{:?}", e))
+                })
+                .map_or_else(
+                    |e| format!("Unable to read response: {}", e),
+                    |v| format!("[{}] {}", v.error.code, v.error.message),
+                ),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    /// The zone id is accepted both bare (`Z123`) and as returned by the API (`/hostedzone/Z123`).
+    fn zone_path(&self) -> String {
+        format!(
+            "/2013-04-01/hostedzone/{}",
+            self.config
+                .hosted_zone_id
+                .trim_start_matches("/hostedzone/")
+        )
+    }
+
+    async fn send_signed(
+        &self,
+        method: &str,
+        url: Url,
+        body: Option<String>,
+    ) -> color_eyre::Result<Response> {
+        let timestamp = Utc::now();
+        let host = url.host_str().wrap_err("Base URL has no host")?.to_string();
+        let amz_date = timestamp.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload = body.as_deref().unwrap_or("");
+        let authorization = self.signer.authorization(
+            method,
+            &url,
+            &[("host", host.as_str()), ("x-amz-date", amz_date.as_str())],
+            payload.as_bytes(),
+            timestamp,
+        );
+
+        let request = match method {
+            "GET" => self.client.get(url),
+            "POST" => self.client.post(url),
+            _ => return Err(eyre!("Unsupported method {}", method)),
+        }
+        .header("X-Amz-Date", amz_date)
+        .header("Authorization", authorization);
+        let request = match body {
+            Some(body) => request.header("Content-Type", "text/xml").body(body),
+            None => request,
+        };
+        request.send().await.wrap_err("Failed to send request")
+    }
+}
+
+impl UpdateDnsCreator for Route53 {
+    type Config = Route53Config;
+    const TAG: &'static str = "route53";
+    const DESCRIPTION: &'static str = "AWS Route 53, by hosted zone, with an access key";
+
+    fn from_config(config: Self::Config) -> Self {
+        let signer = SigV4Signer {
+            access_key_id: config.access_key_id.clone(),
+            secret_access_key: config.secret_access_key.to_string(),
+            region: config.region.clone(),
+            service: SERVICE.to_string(),
+        };
+        Route53 {
+            client: config.http.client(),
+            config,
+            signer,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Route53 {
+    fn describe(&self) -> String {
+        format!(
+            "Route53[hosted_zone={zone_id}]",
+            zone_id = &self.config.hosted_zone_id
+        )
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        // Route 53 always reports names fully qualified
+        let fqdn = format!("{}.", name.trim_end_matches('.'));
+
+        // GET the record set at `name`, the listing starts at the given name/type so it may be
+        // some other set if ours doesn't exist
+        let mut url = Url::parse(&format!(
+            "{base}{zone}/rrset",
+            base = self.config.base_url,
+            zone = self.zone_path(),
+        ))
+        .wrap_err("Failed to build URL")?;
+        url.set_query(Some(&canonical_query_string(&url_query(&[
+            ("maxitems", "1"),
+            ("name", &fqdn),
+            ("type", record_type),
+        ]))));
+        let response = self.send_signed("GET", url, None).await?;
+        if !response.status().is_success() {
+            return Err(Route53::create_route53_error(response).await);
+        }
+
+        let body = response.text().await.wrap_err("Failed to read response")?;
+        let list: Route53ListResourceRecordSetsRes =
+            quick_xml::de::from_str(&body).wrap_err("Failed to read response")?;
+        let existing = list
+            .resource_record_sets
+            .sets
+            .into_iter()
+            .find(|set| set.name.eq_ignore_ascii_case(&fqdn) && set.record_type == record_type);
+
+        let old_ip = existing
+            .as_ref()
+            .and_then(|set| set.resource_records.records.first())
+            .and_then(|r| r.value.parse().ok());
+        let ttl = match &existing {
+            Some(set) => {
+                let values: Vec<&str> = set
+                    .resource_records
+                    .records
+                    .iter()
+                    .map(|r| r.value.as_str())
+                    .collect();
+                let ttl = self.config.ttl.or(set.ttl).unwrap_or_else(default_ttl);
+                if values == [new_ip.to_string()] && set.ttl == Some(ttl) {
+                    info!(
+                        "[route53] New IP is the same as existing {} record, skipping update.",
+                        record_type
+                    );
+                    return Ok(UpdateOutcome::unchanged(new_ip));
+                }
+                info!(
+                    "[route53] Old {} content was {} with TTL {:?}",
+                    record_type,
+                    values.join(", "),
+                    set.ttl
+                );
+                ttl
+            }
+            None => {
+                info!(
+                    "[route53] No existing {} record, creating one.",
+                    record_type
+                );
+                self.config.ttl.unwrap_or_else(default_ttl)
+            }
+        };
+
+        if dry_run {
+            info!(
+                "[route53] Dry run, would set {} {} record to {}",
+                fqdn, record_type, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        let url = Url::parse(&format!(
+            "{base}{zone}/rrset/",
+            base = self.config.base_url,
+            zone = self.zone_path(),
+        ))
+        .wrap_err("Failed to build URL")?;
+        let body = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ChangeResourceRecordSetsRequest xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+<ChangeBatch><Changes><Change><Action>UPSERT</Action><ResourceRecordSet><Name>{name}</Name><Type>{record_type}</Type><TTL>{ttl}</TTL><ResourceRecords><ResourceRecord><Value>{value}</Value></ResourceRecord></ResourceRecords></ResourceRecordSet></Change></Changes></ChangeBatch>
+</ChangeResourceRecordSetsRequest>"#,
+            name = fqdn,
+            record_type = record_type,
+            ttl = ttl,
+            value = new_ip,
+        );
+        let response = self.send_signed("POST", url, Some(body)).await?;
+        if !response.status().is_success() {
+            return Err(Route53::create_route53_error(response).await);
+        }
+
+        let body = response.text().await.wrap_err("Failed to read response")?;
+        let change: Route53ChangeResourceRecordSetsRes =
+            quick_xml::de::from_str(&body).wrap_err("Failed to read response")?;
+        info!(
+            "Successful: change {} is {}",
+            change.change_info.id, change.change_info.status
+        );
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+/// AWS Signature Version 4, as described at
+/// <https://docs.aws.amazon.com/general/latest/gr/sigv4_signing.html>.
+struct SigV4Signer {
+    access_key_id: String,
+    secret_access_key: String,
+    region: String,
+    service: String,
+}
+
+impl SigV4Signer {
+    fn credential_scope(&self, timestamp: DateTime<Utc>) -> String {
+        format!(
+            "{date}/{region}/{service}/aws4_request",
+            date = timestamp.format("%Y%m%d"),
+            region = self.region,
+            service = self.service,
+        )
+    }
+
+    /// `headers` must be the exact headers sent with the request; names are lowercased and
+    /// sorted here.
+    fn canonical_request(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> String {
+        let mut headers: Vec<(String, &str)> = headers
+            .iter()
+            .map(|(k, v)| (k.to_ascii_lowercase(), v.trim()))
+            .collect();
+        headers.sort();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect();
+        let signed_headers = signed_headers(&headers);
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+
+        format!(
+            "{method}\n{path}\n{query}\n{headers}\n{signed_headers}\n{payload}",
+            method = method,
+            path = url.path(),
+            query = canonical_query_string(&query),
+            headers = canonical_headers,
+            signed_headers = signed_headers,
+            payload = hex::encode(Sha256::digest(payload)),
+        )
+    }
+
+    fn string_to_sign(&self, timestamp: DateTime<Utc>, canonical_request: &str) -> String {
+        format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{hash}",
+            timestamp = timestamp.format("%Y%m%dT%H%M%SZ"),
+            scope = self.credential_scope(timestamp),
+            hash = hex::encode(Sha256::digest(canonical_request.as_bytes())),
+        )
+    }
+
+    fn signature(&self, timestamp: DateTime<Utc>, string_to_sign: &str) -> String {
+        let date = timestamp.format("%Y%m%d").to_string();
+        let key = hmac_sha256(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, self.service.as_bytes());
+        let key = hmac_sha256(&key, b"aws4_request");
+        hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()))
+    }
+
+    /// Computes the `Authorization` header value for a request.
+    fn authorization(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+        timestamp: DateTime<Utc>,
+    ) -> String {
+        let canonical_request = self.canonical_request(method, url, headers, payload);
+        let string_to_sign = self.string_to_sign(timestamp, &canonical_request);
+        let mut header_names: Vec<(String, &str)> = headers
+            .iter()
+            .map(|(k, v)| (k.to_ascii_lowercase(), *v))
+            .collect();
+        header_names.sort();
+        format!(
+            "AWS4-HMAC-SHA256 Credential={access_key}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            access_key = self.access_key_id,
+            scope = self.credential_scope(timestamp),
+            signed_headers = signed_headers(&header_names),
+            signature = self.signature(timestamp, &string_to_sign),
+        )
+    }
+}
+
+fn signed_headers(sorted_headers: &[(String, &str)]) -> String {
+    sorted_headers
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn url_query(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Sorted and encoded with AWS's flavor of percent-encoding, so the URL sent matches what was
+/// signed.
+fn canonical_query_string(pairs: &[(String, String)]) -> String {
+    let mut encoded: Vec<(String, String)> = pairs
+        .iter()
+        .map(|(k, v)| (aws_uri_encode(k), aws_uri_encode(v)))
+        .collect();
+    encoded.sort();
+    encoded
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn aws_uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53ErrorResponse {
+    #[serde(rename = "Error")]
+    error: Route53Error,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53Error {
+    #[serde(rename = "Code")]
+    code: String,
+    #[serde(rename = "Message")]
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53ListResourceRecordSetsRes {
+    #[serde(rename = "ResourceRecordSets")]
+    resource_record_sets: Route53ResourceRecordSets,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53ResourceRecordSets {
+    #[serde(rename = "ResourceRecordSet", default)]
+    sets: Vec<Route53ResourceRecordSet>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53ResourceRecordSet {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Type")]
+    record_type: String,
+    #[serde(rename = "TTL")]
+    ttl: Option<u32>,
+    #[serde(rename = "ResourceRecords", default)]
+    resource_records: Route53ResourceRecords,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Route53ResourceRecords {
+    #[serde(rename = "ResourceRecord", default)]
+    records: Vec<Route53ResourceRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53ResourceRecord {
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53ChangeResourceRecordSetsRes {
+    #[serde(rename = "ChangeInfo")]
+    change_info: Route53ChangeInfo,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route53ChangeInfo {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "Status")]
+    status: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    // Inputs from the worked example in the AWS SigV4 documentation
+    fn example_signer() -> SigV4Signer {
+        SigV4Signer {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            region: "us-east-1".to_string(),
+            service: "iam".to_string(),
+        }
+    }
+
+    fn example_timestamp() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap()
+    }
+
+    const EXAMPLE_HEADERS: &[(&str, &str)] = &[
+        (
+            "Content-Type",
+            "application/x-www-form-urlencoded; charset=utf-8",
+        ),
+        ("Host", "iam.amazonaws.com"),
+        ("X-Amz-Date", "20150830T123600Z"),
+    ];
+
+    fn example_url() -> Url {
+        Url::parse("https://iam.amazonaws.com/?Version=2010-05-08&Action=ListUsers").unwrap()
+    }
+
+    #[test]
+    fn canonical_request_matches_aws_example() {
+        let canonical_request =
+            example_signer().canonical_request("GET", &example_url(), EXAMPLE_HEADERS, b"");
+
+        assert_eq!(
+            canonical_request,
+            "GET\n\
+             /\n\
+             Action=ListUsers&Version=2010-05-08\n\
+             content-type:application/x-www-form-urlencoded; charset=utf-8\n\
+             host:iam.amazonaws.com\n\
+             x-amz-date:20150830T123600Z\n\
+             \n\
+             content-type;host;x-amz-date\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn string_to_sign_matches_aws_example() {
+        let signer = example_signer();
+        let canonical_request =
+            signer.canonical_request("GET", &example_url(), EXAMPLE_HEADERS, b"");
+
+        assert_eq!(
+            signer.string_to_sign(example_timestamp(), &canonical_request),
+            "AWS4-HMAC-SHA256\n\
+             20150830T123600Z\n\
+             20150830/us-east-1/iam/aws4_request\n\
+             f536975d06c0309214f805bb90ccff089219ecd68b2577efef23edd43b7e1a59"
+        );
+    }
+
+    #[test]
+    fn authorization_matches_aws_example() {
+        let authorization = example_signer().authorization(
+            "GET",
+            &example_url(),
+            EXAMPLE_HEADERS,
+            b"",
+            example_timestamp(),
+        );
+
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn query_string_is_aws_encoded() {
+        assert_eq!(
+            canonical_query_string(&url_query(&[
+                ("type", "A"),
+                ("name", "home.example.com."),
+                ("maxitems", "1"),
+                ("odd", "a b/c"),
+            ])),
+            "maxitems=1&name=home.example.com.&odd=a%20b%2Fc&type=A"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use log::info;
+use serde::Deserialize;
+
+use crate::update_dns::api::{UpdateDns, UpdateDnsCreator, UpdateOutcome, UpdateResult};
+
+/// Prints each update instead of making it, for scripts and for trying out a config without any
+/// credentials.
+pub struct StdoutDns {
+    config: StdoutConfig,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StdoutConfig {
+    /// Print a JSON object per update instead of `name -> ip`.
+    #[serde(default)]
+    pub json: bool,
+}
+
+impl StdoutDns {
+    fn line(&self, name: &str, new_ip: IpAddr) -> String {
+        if self.config.json {
+            serde_json::json!({ "dns_name": name, "ip": new_ip }).to_string()
+        } else {
+            format!("{} -> {}", name, new_ip)
+        }
+    }
+}
+
+impl UpdateDnsCreator for StdoutDns {
+    type Config = StdoutConfig;
+    const TAG: &'static str = "stdout";
+    const DESCRIPTION: &'static str = "Prints each update instead of making it, for testing";
+
+    fn from_config(config: Self::Config) -> Self {
+        StdoutDns { config }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for StdoutDns {
+    fn describe(&self) -> String {
+        "Stdout".to_string()
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        if dry_run {
+            info!("[stdout] Dry run, would print {}", self.line(&name, new_ip));
+            return Ok(UpdateOutcome::not_written(None, new_ip));
+        }
+
+        println!("{}", self.line(&name, new_ip));
+
+        Ok(UpdateOutcome::written(None, new_ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_line_has_name_and_ip() {
+        let stdout = StdoutDns::from_config(StdoutConfig { json: true });
+
+        let line = stdout.line("a.example.com", "203.0.113.5".parse().unwrap());
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            serde_json::json!({ "dns_name": "a.example.com", "ip": "203.0.113.5" })
+        );
+    }
+}
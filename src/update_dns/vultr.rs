@@ -0,0 +1,174 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, WrapErr};
+use log::info;
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+use crate::http::HttpOptions;
+use crate::update_dns::api::{
+    relative_name, UpdateDns, UpdateDnsCreator, UpdateDnsError, UpdateOutcome, UpdateResult,
+};
+
+pub struct Vultr {
+    config: VultrConfig,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VultrConfig {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub api_key: Secret<String>,
+    pub domain: String,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+fn default_base_url() -> String {
+    "https://api.vultr.com/v2".to_string()
+}
+
+impl Vultr {
+    async fn create_vultr_error(response: Response) -> UpdateDnsError {
+        let status = response.status();
+        let report = eyre!(
+            "{status} Error from Vultr: {de}",
+            status = status,
+            de = response
+                .json::<VultrError>()
+                .await
+                .map_or_else(|e| format!("Unable to read response: {:?}", e), |v| v.error),
+        );
+        UpdateDnsError::from_status(status, report)
+    }
+
+    /// Vultr wants the name relative to the domain, empty for the domain itself.
+    fn record_name(&self, name: &str) -> color_eyre::Result<String> {
+        relative_name(name, &self.config.domain, "")
+            .ok_or_else(|| eyre!("{} is not in domain {}", name, self.config.domain))
+    }
+}
+
+impl UpdateDnsCreator for Vultr {
+    type Config = VultrConfig;
+    const TAG: &'static str = "vultr";
+    const DESCRIPTION: &'static str = "Vultr DNS, by domain";
+
+    fn from_config(config: Self::Config) -> Self {
+        Vultr {
+            client: config.http.client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateDns for Vultr {
+    fn describe(&self) -> String {
+        format!("Vultr[domain={domain}]", domain = &self.config.domain)
+    }
+
+    async fn update_dns(&self, name: String, new_ip: IpAddr, dry_run: bool) -> UpdateResult {
+        let record_type = match new_ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+        let record_name = self.record_name(&name)?;
+        let records_url = format!(
+            "{base}/domains/{domain}/records",
+            base = self.config.base_url,
+            domain = &self.config.domain,
+        );
+
+        // GET all records in the domain, the API can't filter by name or type
+        let response = self
+            .client
+            .get(&records_url)
+            .bearer_auth(self.config.api_key.as_str())
+            .query(&[("per_page", "500")])
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Vultr::create_vultr_error(response).await);
+        }
+
+        let list: VultrListRecordsRes =
+            response.json().await.wrap_err("Failed to read response")?;
+        let matching: Vec<&VultrRecord> = list
+            .records
+            .iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(&record_name) && r.record_type == record_type)
+            .collect();
+        let record = match matching.as_slice() {
+            [r] => r,
+            _ => return Err(eyre!("Expected exactly one result, got {:?}", matching).into()),
+        };
+
+        if record.data == new_ip.to_string() {
+            info!(
+                "[vultr] New IP is the same as existing {} record, skipping update.",
+                record_type
+            );
+            return Ok(UpdateOutcome::unchanged(new_ip));
+        }
+
+        let old_ip = record.data.parse().ok();
+        info!("[vultr] Old {} content was {}", record_type, record.data);
+
+        if dry_run {
+            info!(
+                "[vultr] Dry run, would update {} from {} to {}",
+                name, record.data, new_ip
+            );
+            return Ok(UpdateOutcome::not_written(old_ip, new_ip));
+        }
+
+        // PATCH responds with no content, so there's nothing to read back on success
+        let response = self
+            .client
+            .patch(format!("{}/{}", records_url, record.id))
+            .bearer_auth(self.config.api_key.as_str())
+            .json(&VultrUpdateRecordReq {
+                data: new_ip.to_string(),
+            })
+            .send()
+            .await
+            .wrap_err("Failed to send request")?;
+        if !response.status().is_success() {
+            return Err(Vultr::create_vultr_error(response).await);
+        }
+
+        info!("Successful: updated record {}", record.id);
+
+        Ok(UpdateOutcome::written(old_ip, new_ip))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct VultrError {
+    error: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct VultrListRecordsRes {
+    #[serde(default)]
+    records: Vec<VultrRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+struct VultrRecord {
+    id: String,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct VultrUpdateRecordReq {
+    data: String,
+}